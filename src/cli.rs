@@ -0,0 +1,735 @@
+// 命令行入口：encrypt / decrypt / list / verify / info / keygen / add-recipient / upgrade
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pw::encryptor::{self, ArchiveFormat, CheckStatus, Compression, ConflictPolicy, EncryptorError, EntryKind, Identity, Kdf, Recipient};
+
+// 退出码，脚本可以据此区分失败原因
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+const EXIT_WRONG_PASSWORD: u8 = 3;
+const EXIT_CORRUPTED: u8 = 4;
+const EXIT_SOURCE_MISSING: u8 = 5;
+const EXIT_UNSUPPORTED: u8 = 6;
+const EXIT_INVALID_OPTIONS: u8 = 7;
+const EXIT_CANCELLED: u8 = 8;
+const EXIT_OUTPUT_EXISTS: u8 = 9;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  I/O or other error
+  2  invalid command line
+  3  incorrect password, or no matching identity
+  4  archive is corrupted or has been modified
+  5  input does not exist
+  6  not a supported archive
+  7  invalid options
+  8  cancelled
+  9  output already exists";
+
+#[derive(Parser)]
+#[command(name = "pw", version, about = "Encrypt files and folders into password-protected archives")]
+#[command(after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    // 为空时启动图形界面
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Encrypt files and folders into one archive (<name>.aes next to a single input, archive.aes for several; .tar.age with --format age)
+    Encrypt {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Where to save the archive (a file path, or a folder to save it into)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Container format; decryption detects it automatically
+        #[arg(long, value_enum, default_value_t = Format::Native)]
+        format: Format,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        recipients: RecipientArgs,
+        /// Also protect the archive with a password when recipients are given
+        #[arg(short = 'p', long)]
+        passphrase: bool,
+        #[command(flatten)]
+        kdf: KdfArgs,
+        #[command(flatten)]
+        compression: CompressionArgs,
+        /// Record files as owned by root instead of their actual user and group
+        #[arg(long)]
+        no_owner: bool,
+        /// What to do if the archive already exists
+        #[arg(long, value_enum, default_value_t = Conflict::Fail)]
+        on_conflict: Conflict,
+    },
+    /// Decrypt an archive (by default into the directory that contains it)
+    Decrypt {
+        archive: PathBuf,
+        /// Only extract these archive paths or glob patterns (`*`, `?`, `**`); prefix a pattern with `!` to exclude what it matches. Quote patterns so the shell does not expand them
+        #[arg(value_name = "PATH")]
+        select: Vec<String>,
+        /// Folder to extract into (created if missing)
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        identities: IdentityArgs,
+        /// Do not restore file owners; extracted files belong to the current user
+        #[arg(long)]
+        no_owner: bool,
+        /// What to do if the extracted folder already exists
+        #[arg(long, value_enum, default_value_t = Conflict::Fail)]
+        on_conflict: Conflict,
+    },
+    /// List the entries of an archive without extracting it
+    List {
+        archive: PathBuf,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        identities: IdentityArgs,
+    },
+    /// Check that archives decrypt and are intact, file by file, without writing files
+    Verify {
+        /// Archives to check, all with the same password
+        #[arg(required = true)]
+        archives: Vec<PathBuf>,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        identities: IdentityArgs,
+        /// Only print failures and the summary
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Show the format, recipients and key derivation parameters of an archive
+    Info { archive: PathBuf },
+    /// Generate an X25519 identity; its public key (age1...) can be given to `encrypt -r`
+    Keygen {
+        /// Write the identity to this file instead of standard output
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Let more people open an archive, without re-encrypting its contents
+    AddRecipient {
+        archive: PathBuf,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        identities: IdentityArgs,
+        #[command(flatten)]
+        recipients: RecipientArgs,
+        /// Add a new password (asked for on the terminal)
+        #[arg(long)]
+        add_password: bool,
+        #[command(flatten)]
+        kdf: KdfArgs,
+    },
+    /// Re-encrypt an archive in the current format, e.g. .aes files made with openssl by older versions
+    ///
+    /// Replaces the archive unless --output is given. The password is kept; public-key recipients
+    /// are not stored in archives and must be given again with -r or -R.
+    Upgrade {
+        archive: PathBuf,
+        /// Save the converted archive here instead of replacing the original (a file path, or a folder to save it into)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Format of the converted archive
+        #[arg(long, value_enum, default_value_t = Format::Native)]
+        format: Format,
+        #[command(flatten)]
+        password: PasswordArgs,
+        #[command(flatten)]
+        identities: IdentityArgs,
+        #[command(flatten)]
+        recipients: RecipientArgs,
+        #[command(flatten)]
+        kdf: KdfArgs,
+        #[command(flatten)]
+        compression: CompressionArgs,
+        /// Record files as owned by root instead of their recorded user and group
+        #[arg(long)]
+        no_owner: bool,
+        /// What to do if the --output file already exists
+        #[arg(long, value_enum, default_value_t = Conflict::Fail)]
+        on_conflict: Conflict,
+    },
+}
+
+// 加密给这些公钥；可与密码同时使用
+#[derive(Args)]
+pub struct RecipientArgs {
+    /// Encrypt to this public key (age1... or "ssh-ed25519 AAAA..."); can be repeated
+    #[arg(short, long = "recipient", value_name = "KEY")]
+    recipient: Vec<String>,
+    /// Encrypt to every public key in this file, one per line; can be repeated
+    #[arg(short = 'R', long = "recipients-file", value_name = "PATH")]
+    recipients_file: Vec<PathBuf>,
+}
+
+impl RecipientArgs {
+    fn read(&self) -> Result<Vec<Recipient>, CliError> {
+        let mut recipients = self.recipient.iter().map(|key| Recipient::parse(key)).collect::<Result<Vec<_>, _>>()?;
+        for path in &self.recipients_file {
+            recipients.extend(Recipient::read_file(path)?);
+        }
+        Ok(recipients)
+    }
+}
+
+// 解密用的身份文件
+#[derive(Args)]
+pub struct IdentityArgs {
+    /// Decrypt with the private key in this file (age identity or unencrypted ssh-ed25519 key); can be repeated
+    #[arg(short, long = "identity", value_name = "PATH")]
+    identity: Vec<PathBuf>,
+}
+
+impl IdentityArgs {
+    fn read(&self) -> Result<Vec<Identity>, CliError> {
+        let mut identities = Vec::new();
+        for path in &self.identity {
+            identities.extend(Identity::read_file(path)?);
+        }
+        Ok(identities)
+    }
+}
+
+// 密码来源，最多指定一个；都未指定时在终端提示输入
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct PasswordArgs {
+    /// Read the password from this environment variable
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+    /// Read the password from the first line of this file descriptor
+    #[arg(long, value_name = "FD")]
+    password_fd: Option<i32>,
+    /// Read the password from the first line of this file
+    #[arg(long, value_name = "PATH")]
+    password_file: Option<PathBuf>,
+}
+
+impl PasswordArgs {
+    fn given(&self) -> bool {
+        self.password_env.is_some() || self.password_fd.is_some() || self.password_file.is_some()
+    }
+}
+
+// 输出已存在时的处理方式，对应 ConflictPolicy
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Conflict {
+    /// Stop with an error
+    Fail,
+    /// Write to "name (1)", "name (2)", ...
+    Rename,
+    /// Replace the existing file or folder
+    Overwrite,
+    /// Extract into the existing folder, replacing files with the same name
+    Merge,
+}
+
+impl From<Conflict> for ConflictPolicy {
+    fn from(conflict: Conflict) -> Self {
+        match conflict {
+            Conflict::Fail => ConflictPolicy::Fail,
+            Conflict::Rename => ConflictPolicy::Rename,
+            Conflict::Overwrite => ConflictPolicy::Overwrite,
+            Conflict::Merge => ConflictPolicy::Merge,
+        }
+    }
+}
+
+// 容器格式，对应 ArchiveFormat
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// .aes archive with compression and an entry index
+    Native,
+    /// age file (.tar.age) that age or rage can decrypt; uncompressed, and a password cannot be combined with keys
+    Age,
+    /// 7z archive that 7-Zip can open; a single password, Deflate unless --compression none
+    #[value(name = "7z")]
+    SevenZip,
+    /// AES-256 encrypted .zip (WinZip AE-2) for 7-Zip and built-in unzip tools; a single password, file names are visible
+    Zip,
+}
+
+impl From<Format> for ArchiveFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Native => ArchiveFormat::Native,
+            Format::Age => ArchiveFormat::Age,
+            Format::SevenZip => ArchiveFormat::SevenZip,
+            Format::Zip => ArchiveFormat::Zip,
+        }
+    }
+}
+
+// 压缩算法，对应 Compression
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Algorithm {
+    /// Good ratio at reasonable speed
+    Zstd,
+    /// Fastest, lower ratio
+    Lz4,
+    /// Store without compression
+    None,
+}
+
+#[derive(Args)]
+pub struct CompressionArgs {
+    /// Compression applied before encryption; already-compressed files (jpg, mp4, zip, ...) are stored as is
+    #[arg(long, value_enum, default_value_t = Algorithm::Zstd)]
+    compression: Algorithm,
+    /// zstd compression level (1-22)
+    #[arg(long, value_name = "N")]
+    level: Option<i32>,
+}
+
+impl CompressionArgs {
+    fn to_compression(&self) -> Result<Compression, String> {
+        match (self.compression, self.level) {
+            (Algorithm::Zstd, level) => Ok(match level {
+                Some(level) => Compression::Zstd { level },
+                None => Compression::default(),
+            }),
+            (Algorithm::Lz4, None) => Ok(Compression::Lz4),
+            (Algorithm::None, None) => Ok(Compression::None),
+            (_, Some(_)) => Err("--level only applies to zstd compression".to_string()),
+        }
+    }
+}
+
+// Argon2id 参数，未指定的项使用默认值
+#[derive(Args)]
+pub struct KdfArgs {
+    /// Argon2id memory in MiB
+    #[arg(long, value_name = "MIB")]
+    kdf_memory: Option<u32>,
+    /// Argon2id iterations
+    #[arg(long, value_name = "N")]
+    kdf_iterations: Option<u32>,
+    /// Argon2id parallelism
+    #[arg(long, value_name = "N")]
+    kdf_parallelism: Option<u32>,
+    /// Pick parameters so that unlocking takes about this many seconds on this machine
+    #[arg(long, value_name = "SECS", conflicts_with_all = ["kdf_memory", "kdf_iterations", "kdf_parallelism"])]
+    calibrate: Option<f64>,
+}
+
+impl KdfArgs {
+    fn to_kdf(&self) -> Result<Kdf, String> {
+        if let Some(secs) = self.calibrate {
+            let target = Duration::try_from_secs_f64(secs)
+                .ok()
+                .filter(|target| !target.is_zero())
+                .ok_or_else(|| format!("invalid calibration time: {}", secs))?;
+            return Ok(encryptor::calibrate_kdf(target));
+        }
+
+        let Kdf::Argon2id { memory_kib, iterations, parallelism } = Kdf::default() else {
+            unreachable!("default KDF is Argon2id");
+        };
+        let memory_kib = match self.kdf_memory {
+            Some(mib) => mib.checked_mul(1024).ok_or("memory is too large")?,
+            None => memory_kib,
+        };
+        Ok(Kdf::Argon2id {
+            memory_kib,
+            iterations: self.kdf_iterations.unwrap_or(iterations),
+            parallelism: self.kdf_parallelism.unwrap_or(parallelism),
+        })
+    }
+}
+
+pub fn run(command: Command) -> ExitCode {
+    match execute(command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => {
+            eprintln!("Error: {}", message);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(CliError::Encryptor(error)) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(exit_code(&error))
+        }
+        Err(CliError::Failed(code)) => ExitCode::from(code),
+    }
+}
+
+enum CliError {
+    Usage(String),
+    Encryptor(EncryptorError),
+    // 已经输出过说明，只需返回退出码
+    Failed(u8),
+}
+
+impl From<EncryptorError> for CliError {
+    fn from(error: EncryptorError) -> Self {
+        CliError::Encryptor(error)
+    }
+}
+
+fn exit_code(error: &EncryptorError) -> u8 {
+    match error {
+        EncryptorError::WrongPassword | EncryptorError::NoMatchingKey => EXIT_WRONG_PASSWORD,
+        EncryptorError::Corrupted => EXIT_CORRUPTED,
+        EncryptorError::SourceMissing(_) => EXIT_SOURCE_MISSING,
+        EncryptorError::UnsupportedFormat(_) => EXIT_UNSUPPORTED,
+        EncryptorError::InvalidOptions(_) => EXIT_INVALID_OPTIONS,
+        EncryptorError::Cancelled => EXIT_CANCELLED,
+        EncryptorError::OutputExists(_) => EXIT_OUTPUT_EXISTS,
+        EncryptorError::Io { .. } => EXIT_FAILURE,
+        // 以后新增的错误类型
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn execute(command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Encrypt { inputs, output, format, password, recipients, passphrase, kdf, compression, no_owner, on_conflict } => {
+            // 先检查参数再询问密码，避免输完密码才发现参数有误
+            if let Some(missing) = inputs.iter().find(|input| fs::symlink_metadata(input).is_err()) {
+                return Err(EncryptorError::SourceMissing(missing.clone()).into());
+            }
+            let kdf = kdf.to_kdf().map_err(CliError::Usage)?;
+            kdf.validate()?;
+            let compression = compression.to_compression().map_err(CliError::Usage)?;
+            let recipients = recipients.read()?;
+            if format == Format::Age && !recipients.is_empty() && (passphrase || password.given()) {
+                return Err(CliError::Usage("age files cannot have both a password and recipients".to_string()));
+            }
+            if format == Format::SevenZip && !recipients.is_empty() {
+                return Err(CliError::Usage("7z archives can only be protected by a password".to_string()));
+            }
+            if format == Format::Zip && !recipients.is_empty() {
+                return Err(CliError::Usage("zip archives can only be protected by a password".to_string()));
+            }
+            // 指定了接收者时，只有要求时才加上密码
+            let password = if recipients.is_empty() || passphrase || password.given() {
+                read_password(&password, true)?
+            } else {
+                String::new()
+            };
+
+            let options = encryptor::EncryptOptions {
+                format: format.into(),
+                kdf,
+                recipients,
+                compression,
+                destination: output,
+                conflict: on_conflict.into(),
+                skip_ownership: no_owner,
+                ..Default::default()
+            };
+            let report = encryptor::encrypt_paths(&inputs, &password, &options)?;
+            println!(
+                "Encrypted to {} ({} entries, {} bytes)",
+                report.output.display(),
+                report.entries,
+                report.bytes
+            );
+            for path in &report.skipped {
+                eprintln!("Skipped socket {}", path);
+            }
+        }
+        Command::Decrypt { archive, select, output, password, identities, no_owner, on_conflict } => {
+            let (password, identities) = read_credentials(&password, &identities)?;
+            let options = encryptor::DecryptOptions {
+                identities,
+                destination: output,
+                conflict: on_conflict.into(),
+                select,
+                skip_ownership: no_owner,
+                ..Default::default()
+            };
+            let report = encryptor::decrypt_folder(&archive, &password, &options)?;
+            let entries = report.entries.map(|n| format!(" ({} entries)", n)).unwrap_or_default();
+            println!("Decrypted to {}{}", report.output.display(), entries);
+            for rejected in &report.rejected {
+                eprintln!("Skipped unsafe entry {}: {}", rejected.path, rejected.reason);
+            }
+        }
+        Command::List { archive, password, identities } => {
+            let (password, identities) = read_credentials(&password, &identities)?;
+            for entry in encryptor::list_archive(&archive, &password, &identities)? {
+                let kind = match entry.kind {
+                    EntryKind::File => '-',
+                    EntryKind::Directory => 'd',
+                    EntryKind::Symlink => 'l',
+                    EntryKind::Other => '?',
+                };
+                let permissions = entry.permissions().unwrap_or_else(|| "?".repeat(9));
+                let modified = entry.modified.map(format_time).unwrap_or_else(|| "-".repeat(16));
+                println!("{}{} {:>12} {} {}", kind, permissions, entry.size, modified, entry.path);
+            }
+        }
+        Command::Verify { archives, password, identities, quiet } => {
+            let (password, identities) = read_credentials(&password, &identities)?;
+            // 逐个检查，某个归档出错不影响其余归档；退出码取第一个失败的原因
+            let mut failure = None;
+            for archive in &archives {
+                if let Err(code) = verify(archive, &password, &identities, quiet) {
+                    failure.get_or_insert(code);
+                }
+            }
+            if let Some(code) = failure {
+                return Err(CliError::Failed(code));
+            }
+        }
+        Command::Info { archive } => {
+            let info = encryptor::archive_info(&archive)?;
+            match info.format {
+                ArchiveFormat::Age => println!("Format:         age-encryption.org/v1"),
+                ArchiveFormat::OpenSsl => println!("Format:         OpenSSL (older versions, not authenticated; see `pw upgrade`)"),
+                ArchiveFormat::SevenZip => println!("Format:         7z, version 0.{}", info.version),
+                ArchiveFormat::Zip if info.version > 0 => println!("Format:         zip, WinZip AE-{}", info.version),
+                ArchiveFormat::Zip => println!("Format:         zip"),
+                _ => println!("Format version: {}", info.version),
+            }
+            println!("Cipher:         {}", info.cipher);
+            if !info.methods.is_empty() {
+                println!("Methods:        {}", info.methods.join(", "));
+            }
+            let recipients: Vec<String> = info.recipients.iter().map(ToString::to_string).collect();
+            // 没有加密的 7z、zip 归档没有接收者
+            println!("Recipients:     {}", if recipients.is_empty() { "none".to_string() } else { recipients.join(", ") });
+            match info.kdf {
+                Some(Kdf::Pbkdf2Sha256 { iterations }) => {
+                    println!("Key derivation: PBKDF2-SHA256, {} iterations", iterations);
+                }
+                Some(Kdf::Argon2id { memory_kib, iterations, parallelism }) => {
+                    println!(
+                        "Key derivation: Argon2id, {} MiB, {} iterations, parallelism {}",
+                        memory_kib / 1024,
+                        iterations,
+                        parallelism
+                    );
+                }
+                Some(Kdf::Scrypt { log_n }) => println!("Key derivation: scrypt, N = 2^{}, r = 8, p = 1", log_n),
+                Some(Kdf::SevenZip { log_cycles }) => {
+                    println!("Key derivation: SHA-256, 2^{} iterations (7-Zip)", log_cycles);
+                }
+                Some(Kdf::Pbkdf2Sha1 { iterations }) => {
+                    println!("Key derivation: PBKDF2-SHA1, {} iterations", iterations);
+                }
+                None => {}
+            }
+            println!("Entry index:    {}", if info.indexed { "yes" } else { "no" });
+            // 7z、zip 的压缩方法已在 Methods 中列出
+            if info.methods.is_empty() {
+                println!("Compression:    {}", info.compression);
+            }
+            println!("Size:           {} bytes", info.size);
+        }
+        Command::Keygen { output } => {
+            let identity = Identity::generate();
+            let public_key = identity.recipient().to_string();
+            let contents = format!(
+                "# created: {}\n# public key: {}\n{}\n",
+                format_time(SystemTime::now()),
+                public_key,
+                identity.to_secret_string().expect("generated identities are X25519")
+            );
+            match output {
+                Some(path) => {
+                    write_private(&path, &contents).map_err(|source| {
+                        let context = format!("Failed to write {}", path.display());
+                        CliError::Encryptor(EncryptorError::Io { context, source })
+                    })?;
+                    println!("Public key: {}", public_key);
+                }
+                None => print!("{}", contents),
+            }
+        }
+        Command::AddRecipient { archive, password, identities, recipients, add_password, kdf } => {
+            let mut recipients = recipients.read()?;
+            let kdf = kdf.to_kdf().map_err(CliError::Usage)?;
+            kdf.validate()?;
+            if recipients.is_empty() && !add_password {
+                return Err(CliError::Usage("nothing to add; use -r, -R or --add-password".to_string()));
+            }
+            let (password, identities) = read_credentials(&password, &identities)?;
+            if add_password {
+                let new_password = prompt_password("New password: ", true)?;
+                recipients.push(Recipient::password(&new_password));
+            }
+            let options = encryptor::AddRecipientsOptions { identities, recipients, kdf };
+            let count = encryptor::add_recipients(&archive, &password, &options)?;
+            println!("{} now has {} recipients", archive.display(), count);
+        }
+        Command::Upgrade { archive, output, format, password, identities, recipients, kdf, compression, no_owner, on_conflict } => {
+            let kdf = kdf.to_kdf().map_err(CliError::Usage)?;
+            kdf.validate()?;
+            let compression = compression.to_compression().map_err(CliError::Usage)?;
+            let recipients = recipients.read()?;
+            let (password, identities) = read_credentials(&password, &identities)?;
+            let options = encryptor::UpgradeOptions {
+                identities,
+                encrypt: encryptor::EncryptOptions {
+                    format: format.into(),
+                    kdf,
+                    recipients,
+                    compression,
+                    destination: output,
+                    conflict: on_conflict.into(),
+                    skip_ownership: no_owner,
+                    ..Default::default()
+                },
+            };
+            let report = encryptor::upgrade_archive(&archive, &password, &options)?;
+            println!(
+                "Converted to {} ({} entries, {} bytes)",
+                report.output.display(),
+                report.entries,
+                report.bytes
+            );
+        }
+    }
+    Ok(())
+}
+
+// 解密时的密码和身份：给出身份文件且没有指定密码来源时不再询问密码
+fn read_credentials(password: &PasswordArgs, identities: &IdentityArgs) -> Result<(String, Vec<Identity>), CliError> {
+    let identities = identities.read()?;
+    let password = if identities.is_empty() || password.given() {
+        read_password(password, false)?
+    } else {
+        String::new()
+    };
+    Ok((password, identities))
+}
+
+// 私钥文件只允许本人读写；不覆盖已有文件
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+// 按参数读取密码；confirm 为 true 且从终端输入时要求输入两次
+fn read_password(args: &PasswordArgs, confirm: bool) -> Result<String, CliError> {
+    let io_error = |context: &str| {
+        let context = context.to_string();
+        move |source| CliError::Encryptor(EncryptorError::Io { context, source })
+    };
+
+    if let Some(var) = &args.password_env {
+        return std::env::var(var)
+            .map_err(|_| CliError::Usage(format!("environment variable {} is not set", var)));
+    }
+    if let Some(path) = &args.password_file {
+        let file = File::open(path).map_err(io_error("Failed to open password file"))?;
+        return first_line(file).map_err(io_error("Failed to read password file"));
+    }
+    if let Some(fd) = args.password_fd {
+        return read_password_fd(fd).map_err(io_error("Failed to read password descriptor"));
+    }
+
+    prompt_password("Password: ", confirm)
+}
+
+// 在终端提示输入密码；confirm 为 true 时要求输入两次
+fn prompt_password(prompt: &str, confirm: bool) -> Result<String, CliError> {
+    let io_error = |source| CliError::Encryptor(EncryptorError::Io { context: "Failed to read password".to_string(), source });
+    let password = rpassword::prompt_password(prompt).map_err(io_error)?;
+    if confirm {
+        let again = rpassword::prompt_password("Confirm password: ").map_err(io_error)?;
+        if again != password {
+            return Err(CliError::Usage("passwords do not match".to_string()));
+        }
+    }
+    Ok(password)
+}
+
+#[cfg(unix)]
+fn read_password_fd(fd: i32) -> io::Result<String> {
+    use std::mem::ManuallyDrop;
+    use std::os::fd::FromRawFd;
+
+    if fd < 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file descriptor"));
+    }
+    // 文件描述符由调用方（通常是父进程）传入，可能是标准输入，只借用不关闭
+    // SAFETY: fd 归调用方所有，调用方保证它在本函数执行期间保持打开；ManuallyDrop 保证这里不会关闭它
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    first_line(&*file)
+}
+
+#[cfg(not(unix))]
+fn read_password_fd(_fd: i32) -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "file descriptors are only supported on Unix"))
+}
+
+// 校验一个归档并逐个条目输出结果，失败时返回退出码
+fn verify(archive: &Path, password: &str, identities: &[Identity], quiet: bool) -> Result<(), u8> {
+    println!("{}:", archive.display());
+    let options = encryptor::VerifyOptions { identities: identities.to_vec(), ..Default::default() };
+    let report = match encryptor::verify_archive(archive, password, &options) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("  Error: {}", error);
+            return Err(exit_code(&error));
+        }
+    };
+
+    for result in &report.results {
+        if !result.status.is_ok() {
+            println!("  FAILED {}: {}", result.path, result.status);
+        } else if !quiet {
+            // 只对文件说明没有哈希，目录和链接本来就没有
+            let note = match (result.status, result.kind) {
+                (CheckStatus::Authenticated, EntryKind::File) => " (no stored hash)",
+                _ => "",
+            };
+            println!("  OK     {}{}", result.path, note);
+        }
+    }
+    let failed = report.failures().count();
+    if report.passed() {
+        println!("  Passed: {} entries, {} bytes", report.entries, report.bytes);
+        return Ok(());
+    }
+    let incomplete = if report.complete { "" } else { "; the end of the archive is damaged or missing" };
+    println!("  FAILED: {} of {} entries{}", failed, report.results.len(), incomplete);
+    Err(EXIT_CORRUPTED)
+}
+
+// 读取第一行并去掉行尾换行。逐字节读取，不带缓冲，文件描述符中换行之后的数据留给调用方
+fn first_line(mut file: impl Read) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match file.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "password is not valid UTF-8"))
+}
+
+// 按本地时区显示到分钟
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M").to_string()
+}
@@ -1,260 +1,1233 @@
-use std::fs;
-use std::path::Path;
-use std::process::{Command, Stdio};
-use std::io::Write;
-
-pub fn encrypt_folder(folder_path: &str, password: &str) -> Result<String, String> {
-    let folder_path = Path::new(folder_path);
-    
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::Key;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+mod age;
+mod archive;
+mod cancel;
+mod compress;
+mod error;
+mod extract;
+mod header;
+mod index;
+mod kdf;
+mod lzma;
+mod metadata;
+mod openssl;
+mod output;
+mod progress;
+mod recipient;
+mod select;
+mod sevenz;
+mod stream;
+#[cfg(test)]
+mod testutil;
+mod verify;
+mod vtar;
+mod zip;
+
+use age::{AgeHeader, SingleFile};
+use header::{FORMAT_VERSION, Header, MAC_LEN};
+use cancel::PartialOutput;
+use compress::{BlockTable, CompressWriter, DecompressReader};
+use index::IndexEntry;
+use openssl::CbcReader;
+use progress::{Counting, Tracker};
+use recipient::FILE_KEY_LEN;
+use select::Selection;
+use sevenz::SevenZipReader;
+use stream::{ChunkReader, EncryptWriter, NONCE_PREFIX_LEN};
+use zip::ZipReader;
+
+pub use cancel::CancelToken;
+pub use compress::{Compression, ZSTD_LEVELS};
+pub use error::EncryptorError;
+pub use extract::{RejectReason, RejectedEntry};
+pub use header::Cipher;
+pub use index::EntryKind;
+pub use kdf::{Kdf, calibrate as calibrate_kdf};
+pub use output::ConflictPolicy;
+pub use progress::{Phase, Progress, ProgressCallback};
+pub use recipient::{Identity, Recipient, RecipientKind};
+pub use verify::{CheckStatus, EntryCheck};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// 加密输出的容器格式；解密时按文件头自动识别
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    // 本工具的 .aes 格式：压缩、条目索引、添加接收者
+    #[default]
+    Native,
+    // age 文件（age-encryption.org/v1），可以用 age、rage 解密。载荷为未压缩的 tar 数据，没有索引；
+    // 密码不能与其他接收者同时使用
+    Age,
+    // 旧版本调用 openssl enc 生成的 .aes 文件（AES-256-CBC，没有认证），只能读取，
+    // 可以用 upgrade_archive 转换为其他格式
+    OpenSsl,
+    // 7z 归档，可以用 7-Zip 打开。只能使用一个密码，密钥派生固定为 SHA-256 迭代 2^19 次；
+    // 内容以 Deflate 压缩（Compression::None 时不压缩），文件名同样加密
+    SevenZip,
+    // 采用 WinZip AES（AE-2、AES-256）加密的 zip 归档，可以用 7-Zip 和系统自带的解压工具打开。只能使用一个密码；
+    // 文件各自以 Deflate 压缩（Compression::None 时不压缩），文件名不加密
+    Zip,
+}
+
+impl ArchiveFormat {
+    // 默认输出文件的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Native | ArchiveFormat::OpenSsl => "aes",
+            ArchiveFormat::Age => "tar.age",
+            ArchiveFormat::SevenZip => "7z",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+// 加密参数，密钥派生参数会写入头部供解密时使用
+#[derive(Clone, Default)]
+pub struct EncryptOptions {
+    pub format: ArchiveFormat,
+    // 密码接收者使用的密钥派生参数；age 格式总是使用 scrypt，不是 scrypt 时改用 age 的默认参数
+    pub kdf: Kdf,
+    // 除密码参数外，还能打开归档的接收者（其他密码、X25519 或 ssh-ed25519 公钥）
+    pub recipients: Vec<Recipient>,
+    // 加密前的压缩算法，默认 zstd 级别 3；已压缩的文件（jpg、mp4、zip 等）自动跳过。age 格式不压缩
+    pub compression: Compression,
+    pub progress: Option<ProgressCallback>,
+    pub cancel: Option<CancelToken>,
+    // 加密文件的保存路径；为已有文件夹时保存为其中的 <name>.aes。默认保存在源文件夹旁边
+    pub destination: Option<PathBuf>,
+    // 加密文件已存在时的处理方式
+    pub conflict: ConflictPolicy,
+    // 不记录文件的属主（uid/gid 记为 0），分享给其他机器时不暴露本机的用户和组
+    pub skip_ownership: bool,
+}
+
+// 解密参数
+#[derive(Clone, Default)]
+pub struct DecryptOptions {
+    pub progress: Option<ProgressCallback>,
+    pub cancel: Option<CancelToken>,
+    // 解压到的文件夹，不存在时自动创建。默认为加密文件所在目录
+    pub destination: Option<PathBuf>,
+    // 解压出的文件夹已存在时的处理方式
+    pub conflict: ConflictPolicy,
+    // 只解压这些归档路径或通配符（"*"、"?"、"**"）匹配的条目，选中文件夹即包括其全部内容。
+    // 以 "!" 开头的模式排除匹配的条目。解压结果保留归档中的完整路径。为空时解压全部内容
+    pub select: Vec<String>,
+    // 不还原属主，解压出的文件属于当前用户。普通用户无权修改属主，此时总是跳过
+    pub skip_ownership: bool,
+    // 解密用的私钥，与密码一起尝试
+    pub identities: Vec<Identity>,
+}
+
+// 校验参数
+#[derive(Clone, Default)]
+pub struct VerifyOptions {
+    pub progress: Option<ProgressCallback>,
+    pub cancel: Option<CancelToken>,
+    pub identities: Vec<Identity>,
+}
+
+// 为已有归档添加接收者的参数
+#[derive(Clone, Default)]
+pub struct AddRecipientsOptions {
+    // 用于解开文件密钥的私钥，与密码一起尝试
+    pub identities: Vec<Identity>,
+    // 要添加的接收者
+    pub recipients: Vec<Recipient>,
+    // 新增密码接收者使用的密钥派生参数
+    pub kdf: Kdf,
+}
+
+// 把归档转换为新格式的参数
+#[derive(Clone, Default)]
+pub struct UpgradeOptions {
+    // 用于打开原归档的私钥，与密码一起尝试
+    pub identities: Vec<Identity>,
+    // 新归档的格式、接收者、压缩等参数；destination 为空时替换原文件
+    pub encrypt: EncryptOptions,
+}
+
+// 加密完成后的结果
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct EncryptReport {
+    pub output: PathBuf,
+    // 归档中的条目数（文件、目录、链接）
+    pub entries: usize,
+    // 归档中文件内容的总字节数
+    pub bytes: u64,
+    // 无法打包而跳过的套接字在归档中的路径
+    pub skipped: Vec<String>,
+}
+
+// 解密完成后的结果
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DecryptReport {
+    // 解压出的顶层文件或文件夹（按冲突策略可能被改名）；归档含多个顶层条目时为输出文件夹
+    pub output: PathBuf,
+    // age、OpenSSL 文件没有索引，无法得知条目数
+    pub entries: Option<usize>,
+    // 因路径或类型不安全而没有解压的条目
+    pub rejected: Vec<RejectedEntry>,
+}
+
+// 归档中的一个条目
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    // 文件内容的字节数，其他类型为 0
+    pub size: u64,
+    // 修改时间，tar 头中的值无法解析时为 None
+    pub modified: Option<SystemTime>,
+    // Unix 权限位（如 0o755），同上
+    pub mode: Option<u32>,
+}
+
+impl ArchiveEntry {
+    // ls 风格的权限字符串，如 "rwxr-xr-x"；setuid、setgid、sticky 位显示在对应的执行位上，如 "rwsr-xr-x"
+    pub fn permissions(&self) -> Option<String> {
+        let mode = self.mode?;
+        Some(
+            (0..9)
+                .map(|bit| {
+                    let set = mode & (0o400 >> bit) != 0;
+                    // 各组执行位对应的特殊位：setuid、setgid、sticky
+                    let special = bit % 3 == 2 && mode & (0o4000 >> (bit / 3)) != 0;
+                    match (set, special) {
+                        (true, true) => ['s', 's', 't'][bit / 3],
+                        (false, true) => ['S', 'S', 'T'][bit / 3],
+                        (true, false) => ['r', 'w', 'x'][bit % 3],
+                        (false, false) => '-',
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<IndexEntry> for ArchiveEntry {
+    fn from(entry: IndexEntry) -> Self {
+        ArchiveEntry {
+            path: entry.path,
+            kind: entry.kind,
+            size: entry.size,
+            modified: entry.mtime.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            mode: entry.mode,
+        }
+    }
+}
+
+// 校验结果
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct VerifyReport {
+    pub entries: usize,
+    pub bytes: u64,
+    // 每个条目的校验结果，按归档中的顺序
+    pub results: Vec<EntryCheck>,
+    // 整个归档都检查到了，包括末尾的结束标记和最后一块。为 false 时文件可能被截断，
+    // 或者没有索引的归档在损坏处之后无法继续读取
+    pub complete: bool,
+}
+
+impl VerifyReport {
+    // 所有条目和归档末尾均校验通过
+    pub fn passed(&self) -> bool {
+        self.complete && self.results.iter().all(|result| result.status.is_ok())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &EntryCheck> {
+        self.results.iter().filter(|result| !result.status.is_ok())
+    }
+}
+
+// 头部记录的归档格式信息
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ArchiveInfo {
+    pub format: ArchiveFormat,
+    // age 文件为 1，OpenSSL 文件为 0
+    pub version: u8,
+    pub cipher: Cipher,
+    // 第一个密码接收者的密钥派生参数，没有密码接收者时为 None
+    pub kdf: Option<Kdf>,
+    // 能打开归档的接收者类型，7z、zip 和 OpenSSL 文件只有一个密码
+    pub recipients: Vec<RecipientKind>,
+    // 是否带有条目索引（可快速列目录、随机访问）
+    pub indexed: bool,
+    pub compression: Compression,
+    // 归档文件大小
+    pub size: u64,
+    // 7z 归档使用的编码方法（如 LZMA2、BCJ、7zAES），头部加密时只能看到解码头部的方法；
+    // zip 归档的压缩方法和加密方式（如 Deflate、AES-256）。其他格式为空
+    pub methods: Vec<String>,
+}
+
+pub fn encrypt_folder(folder_path: &Path, password: &str, options: &EncryptOptions) -> Result<EncryptReport, EncryptorError> {
     // 确保文件夹存在
     if !folder_path.is_dir() {
-        return Err(format!("Folder '{}' does not exist", folder_path.display()));
-    }
-    
-    // 获取父目录和文件夹名
-    let parent_dir = folder_path.parent().unwrap_or(Path::new("."));
-    let folder_name = folder_path.file_name()
-        .ok_or("Cannot get folder name")?
-        .to_string_lossy()
-        .to_string();
-    
-    // 在父目录中创建加密文件
-    let encrypted_file = parent_dir.join(format!("{}.aes", folder_name));
-    let encrypted_file_path = encrypted_file.to_string_lossy().to_string();
-    
-    if cfg!(target_os = "macos") || cfg!(target_os = "linux") {
-        // 在 macOS 或 Linux 上使用 tar 和 openssl
-        
-        // 创建临时文件名
-        let temp_tar = parent_dir.join(format!("{}.tar", folder_name));
-        let temp_tar_path = temp_tar.to_string_lossy().to_string();
-        
-        // 获取文件夹的绝对路径和名称
-        let abs_folder_path = folder_path.canonicalize()
-            .map_err(|e| format!("Failed to get absolute path: {}", e))?;
-        let parent_abs_path = abs_folder_path.parent().unwrap_or(Path::new("/"));
-        
-        // 创建一个临时 tar 文件，保留完整目录结构
-        // -C 选项指定父目录作为基准，然后只打包目标文件夹
-        let tar_command = Command::new("tar")
-            .args(&[
-                "-cf", 
-                &temp_tar_path, 
-                "-C", 
-                &parent_abs_path.to_string_lossy().to_string(), 
-                &folder_name
-            ])
-            .output()
-            .map_err(|e| format!("Failed to execute tar command: {}", e))?;
-        
-        if !tar_command.status.success() {
-            return Err(format!("Failed to package folder: {}", String::from_utf8_lossy(&tar_command.stderr)));
+        return Err(EncryptorError::SourceMissing(folder_path.to_path_buf()));
+    }
+    encrypt_paths(&[folder_path.to_path_buf()], password, options)
+}
+
+// 把任意多个文件、文件夹打包加密到一个归档中。每个输入以自己的名称放在归档顶层，
+// 重名时依次改为 "name (2)"、"name (3)" 等。
+// 只有一个输入时默认保存为其旁边的 <name>.aes，多个输入时默认保存为第一个输入旁边的 archive.aes
+// （age 格式为 .tar.age）。password 为空时只加密给 options.recipients 中的接收者。
+pub fn encrypt_paths(inputs: &[PathBuf], password: &str, options: &EncryptOptions) -> Result<EncryptReport, EncryptorError> {
+    let recipients = encrypt_recipients(password, options)?;
+    if inputs.is_empty() {
+        return Err(EncryptorError::InvalidOptions("nothing to encrypt".to_string()));
+    }
+    // 确保所有输入都存在，并确定它们在归档中的名称
+    let mut names: Vec<String> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        if fs::symlink_metadata(input).is_err() {
+            return Err(EncryptorError::SourceMissing(input.clone()));
+        }
+        let name = archive_name(input)?;
+        let name = (1..)
+            .map(|n| if n == 1 { name.clone() } else { format!("{} ({})", name, n) })
+            .find(|candidate| !names.contains(candidate))
+            .expect("an unused name exists");
+        names.push(name);
+    }
+
+    // 未指定时保存在第一个输入旁边；指定的是已有文件夹时保存到其中
+    let default_name = match names.as_slice() {
+        [name] => format!("{}.{}", name, options.format.extension()),
+        _ => format!("archive.{}", options.format.extension()),
+    };
+    let encrypted_file = match &options.destination {
+        Some(destination) if destination.is_dir() => destination.join(&default_name),
+        Some(destination) => destination.clone(),
+        None => parent_dir(&inputs[0]).join(&default_name),
+    };
+    let output_dir = parent_dir(&encrypted_file);
+    if !output_dir.is_dir() {
+        return Err(EncryptorError::SourceMissing(output_dir.to_path_buf()));
+    }
+    // 保存到要加密的文件夹内部会把正在写的文件也打包进去
+    if inputs.iter().any(|input| input.is_dir() && is_inside(output_dir, input)) {
+        return Err(EncryptorError::InvalidOptions(
+            "the encrypted file cannot be saved inside a folder being encrypted".to_string(),
+        ));
+    }
+
+    // 先统计总量，进度才能给出比例和剩余时间
+    let tracker = Tracker::new(options.progress.as_ref(), options.cancel.as_ref(), Phase::Scan);
+    // 目标已存在时先按策略检查一次，尽早失败
+    output::resolve(&encrypted_file, options.conflict)?;
+    for input in inputs {
+        archive::scan(input, &tracker).map_err(tracker.error("Failed to read input"))?;
+    }
+
+    let mut skipped = Vec::new();
+    let (encrypted_file, entries) = write_archive(&encrypted_file, &recipients, options, &tracker, |builder, entries| {
+        for (input, name) in inputs.iter().zip(&names) {
+            archive::append_tree(builder, input, name, !options.skip_ownership, entries, &mut skipped, &tracker)
+                .map_err(tracker.error("Failed to package input"))?;
+        }
+        Ok(())
+    })?;
+
+    Ok(EncryptReport {
+        output: encrypted_file,
+        entries: entries.len(),
+        bytes: entries.iter().map(|entry| entry.size).sum(),
+        skipped,
+    })
+}
+
+// 检查加密参数，返回 password（不为空时）和 options.recipients 组成的接收者列表
+fn encrypt_recipients(password: &str, options: &EncryptOptions) -> Result<Vec<Recipient>, EncryptorError> {
+    options.kdf.validate()?;
+    options.compression.validate()?;
+    if options.format == ArchiveFormat::OpenSsl {
+        return Err(EncryptorError::InvalidOptions("the OpenSSL format can only be read; use native or age".to_string()));
+    }
+    if options.conflict == ConflictPolicy::Merge {
+        return Err(EncryptorError::InvalidOptions("merge is only supported when decrypting".to_string()));
+    }
+
+    let mut recipients = Vec::with_capacity(options.recipients.len() + 1);
+    if !password.is_empty() {
+        recipients.push(Recipient::password(password));
+    }
+    recipients.extend(options.recipients.iter().cloned());
+    if recipients.is_empty() {
+        return Err(EncryptorError::InvalidOptions("no password or recipients given".to_string()));
+    }
+    Ok(recipients)
+}
+
+type ArchiveBuilder = tar::Builder<CompressWriter<PayloadWriter<BufWriter<File>>>>;
+
+// 把 pack 写入的条目打包加密保存到 encrypted_file，返回最终路径（按冲突策略可能被改名）和条目索引。
+// 先写到同目录的临时文件，成功后再移动到最终位置；失败或取消时删除临时文件
+fn write_archive(
+    encrypted_file: &Path,
+    recipients: &[Recipient],
+    options: &EncryptOptions,
+    tracker: &Tracker,
+    pack: impl FnOnce(&mut ArchiveBuilder, &mut Vec<IndexEntry>) -> Result<(), EncryptorError>,
+) -> Result<(PathBuf, Vec<IndexEntry>), EncryptorError> {
+    let temp_file = output::temp_path(parent_dir(encrypted_file));
+    let output = File::create_new(&temp_file)
+        .map_err(EncryptorError::io("Failed to create encrypted file"))?;
+    let mut partial = PartialOutput::default();
+    partial.track(temp_file.clone());
+
+    // 打包结果直接写入加密流，明文不落盘
+    let mut writer = BufWriter::new(output);
+    let sealer = Sealer::begin(&mut writer, recipients, options)?;
+    tracker.check().map_err(tracker.error("Failed to write encrypted file"))?;
+    let compression = match options.format {
+        // 7z、zip 自己压缩，不需要分块压缩
+        ArchiveFormat::Age | ArchiveFormat::SevenZip | ArchiveFormat::Zip => Compression::None,
+        _ => options.compression,
+    };
+    let mut builder = tar::Builder::new(CompressWriter::new(sealer.payload_writer(writer), compression));
+    // 与 tar 命令一致，符号链接按链接本身保存
+    builder.follow_symlinks(false);
+    let mut entries = Vec::new();
+    tracker.set_phase(Phase::Archive);
+    pack(&mut builder, &mut entries)?;
+
+    tracker.set_phase(Phase::Encrypt);
+    let (writer, blocks) = builder.into_inner()
+        .and_then(|writer| writer.finish())
+        .and_then(|(writer, blocks)| Ok((writer.finish()?, blocks)))
+        .map_err(EncryptorError::io("Failed to write encrypted file"))?;
+    tracker.set_phase(Phase::Finalize);
+    sealer.finish(writer, &entries, blocks.as_ref())
+        .and_then(|writer| writer.into_inner().map_err(|e| e.into_error()))
+        .and_then(|file| file.sync_all())
+        .map_err(EncryptorError::io("Failed to write encrypted file"))?;
+
+    let encrypted_file = output::resolve(encrypted_file, options.conflict)?;
+    output::persist(&temp_file, &encrypted_file, options.conflict)
+        .map_err(EncryptorError::io("Failed to move encrypted file into place"))?;
+    partial.commit();
+    Ok((encrypted_file, entries))
+}
+
+pub fn decrypt_folder(encrypted_path: &Path, password: &str, options: &DecryptOptions) -> Result<DecryptReport, EncryptorError> {
+    // 默认解压到加密文件所在目录，tar 中已包含顶层文件夹名
+    let output_dir = options.destination.as_deref().unwrap_or_else(|| parent_dir(encrypted_path));
+
+    let opened = open_file(encrypted_path, password, &options.identities)?;
+    let selection = Selection::new(&options.select);
+
+    // 进度按解密出的 tar 数据计算，总量即载荷明文长度
+    let tracker = Tracker::new(options.progress.as_ref(), options.cancel.as_ref(), Phase::Extract);
+    let mut entries = opened.entries.as_ref().map(|entries| entries.len());
+    tracker.set_totals(entries, Some(opened.payload.plain_len()));
+
+    // 有索引时可以预先知道要解压哪些条目，路径写错或目标已存在时不必等解压完才失败
+    if let Some(index) = &opened.entries {
+        let selected: Vec<&IndexEntry> = match &selection {
+            Some(selection) => index.iter().filter(|entry| selection.matches(&entry.path)).collect(),
+            None => index.iter().collect(),
+        };
+        if let Some(pattern) = selection.as_ref().and_then(Selection::unmatched) {
+            return Err(not_found(pattern));
+        }
+        let mut top_level: Vec<&str> = selected.iter().filter_map(|entry| entry.path.split('/').next()).collect();
+        top_level.dedup();
+        for name in top_level {
+            output::resolve(&output_dir.join(name), options.conflict)?;
+        }
+
+        // 选择性解压时只读取选中条目的内容，其余条目只读 tar 头（至少 512 字节）
+        if selection.is_some() {
+            let bytes = index.len() as u64 * 512 + selected.iter().map(|entry| entry.size.next_multiple_of(512)).sum::<u64>();
+            entries = Some(selected.len());
+            tracker.set_totals(entries, Some(bytes));
+        }
+    }
+
+    // 指定的目标文件夹在确认密码和选中条目后才创建；之后解压失败时删除新建的部分
+    let mut created = PartialOutput::default();
+    if options.destination.is_some() {
+        // 记下最上层不存在的文件夹，create_dir_all 从它开始创建
+        let missing = output_dir.ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .take_while(|path| fs::symlink_metadata(path).is_err())
+            .last();
+        if let Some(missing) = missing {
+            created.track(missing.to_path_buf());
+        }
+        fs::create_dir_all(output_dir).map_err(EncryptorError::io("Failed to create output folder"))?;
+    }
+
+    // 先解压到同目录下的临时文件夹；失败或取消时整个删除
+    let temp_dir = output::temp_path(output_dir);
+    fs::create_dir(&temp_dir).map_err(EncryptorError::io("Failed to create output folder"))?;
+    let mut partial = PartialOutput::default();
+    partial.track(temp_dir.clone());
+
+    let rejected = read_tar(opened.payload, &tracker, "Failed to extract file", |archive| {
+        extract::unpack(archive, &temp_dir, selection.as_ref(), !options.skip_ownership, &tracker)
+    })?;
+    // 没有索引时解压完才知道是否有模式没匹配到
+    if let Some(pattern) = selection.as_ref().and_then(Selection::unmatched) {
+        return Err(not_found(pattern));
+    }
+
+    // 解压成功后把顶层条目逐个移动到最终位置
+    let mut children = fs::read_dir(&temp_dir)
+        .and_then(|children| children.collect::<io::Result<Vec<_>>>())
+        .map_err(EncryptorError::io("Failed to read extracted files"))?;
+    children.sort_by_key(|child| child.file_name());
+    let mut outputs = Vec::new();
+    for child in children {
+        let target = output::resolve(&output_dir.join(child.file_name()), options.conflict)?;
+        output::persist(&child.path(), &target, options.conflict)
+            .map_err(EncryptorError::io("Failed to move extracted files into place"))?;
+        outputs.push(target);
+    }
+    // 临时文件夹此时已为空，随 partial 一起删除
+    drop(partial);
+    created.commit();
+
+    let output = match outputs.as_slice() {
+        [single] => single.clone(),
+        _ => output_dir.to_path_buf(),
+    };
+    Ok(DecryptReport {
+        output,
+        entries,
+        rejected,
+    })
+}
+
+// 列出归档内容，不写任何文件。有索引时只解密索引；age 和 OpenSSL 文件没有索引，需要顺序读取各条目的 tar 头，
+// 但会跳过文件内容所在的块。
+pub fn list_archive(encrypted_path: &Path, password: &str, identities: &[Identity]) -> Result<Vec<ArchiveEntry>, EncryptorError> {
+    let opened = open_file(encrypted_path, password, identities)?;
+    if let Some(entries) = opened.entries {
+        return Ok(entries.into_iter().map(ArchiveEntry::from).collect());
+    }
+
+    let mut archive = tar::Archive::new(opened.payload);
+    let result = (|| {
+        let mut entries = Vec::new();
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            let header = entry.header();
+            let kind = EntryKind::from_tar(header.entry_type());
+            entries.push(ArchiveEntry {
+                path: entry.path()?.to_string_lossy().into_owned(),
+                kind,
+                size: if kind == EntryKind::File { entry.size() } else { 0 },
+                modified: header.mtime().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+                mode: header.mode().ok().map(|mode| mode & 0o7777),
+            });
         }
-        
-        // 使用 openssl 加密 tar 文件
-        let mut openssl_command = Command::new("openssl");
-        openssl_command
-            .arg("enc")
-            .arg("-aes-256-cbc")  // 使用 CBC 模式替代 GCM
-            .arg("-salt")
-            .arg("-pbkdf2")
-            .arg("-pass")
-            .arg("stdin")  // 从标准输入读取密码
-            .arg("-in")
-            .arg(&temp_tar_path)
-            .arg("-out")
-            .arg(&encrypted_file_path)
-            .stdin(Stdio::piped()) // 设置标准输入
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-        
-        // 启动命令并获取子进程
-        let mut child = openssl_command
-            .spawn()
-            .map_err(|e| format!("Failed to start openssl command: {}", e))?;
-        
-        // 将密码写入标准输入
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(password.as_bytes())
-                .map_err(|e| format!("Failed to write password: {}", e))?;
+        Ok(entries)
+    })();
+
+    result.map_err(|e| {
+        if archive.into_inner().auth_failed() {
+            EncryptorError::Corrupted
         } else {
-            return Err("Unable to get openssl standard input".to_string());
+            EncryptorError::io("Failed to read archive")(e)
         }
-        
-        // 等待命令完成
-        let output = child.wait_with_output()
-            .map_err(|e| format!("Failed to wait for openssl command to complete: {}", e))?;
-        
-        if !output.status.success() {
-            return Err(format!("Failed to encrypt file: {}", String::from_utf8_lossy(&output.stderr)));
+    })
+}
+
+// 校验整个归档：检查密码、每一块的认证标签和索引中记录的文件哈希，不写任何文件。
+// 密码错误或索引无法读取时返回错误；个别条目损坏时照常返回，结果中逐条标明
+pub fn verify_archive(encrypted_path: &Path, password: &str, options: &VerifyOptions) -> Result<VerifyReport, EncryptorError> {
+    let opened = open_file(encrypted_path, password, &options.identities)?;
+    let tracker = Tracker::new(options.progress.as_ref(), options.cancel.as_ref(), Phase::Verify);
+    tracker.set_totals(opened.entries.as_ref().map(|entries| entries.len()), Some(opened.payload.plain_len()));
+
+    match opened.entries {
+        Some(index) => verify_indexed(opened.payload, &index, &tracker),
+        None => verify_sequential(opened.payload, &tracker),
+    }
+}
+
+// 有索引：逐个条目定位读取，损坏的块之后仍能继续检查其余条目
+fn verify_indexed<R: Read + Seek>(
+    payload: PayloadReader<R>,
+    index: &[IndexEntry],
+    tracker: &Tracker,
+) -> Result<VerifyReport, EncryptorError> {
+    let mut reader = Counting::new(payload, tracker);
+    let mut report = VerifyReport { entries: 0, bytes: 0, results: Vec::new(), complete: true };
+    for entry in index {
+        tracker.start_entry(&entry.path).map_err(tracker.error("Failed to read archive"))?;
+        let status = match verify::hash_entry(&mut reader, entry) {
+            Ok((digest, bytes)) => {
+                report.bytes += bytes;
+                verify::compare(entry, &digest)
+            }
+            Err(e) => {
+                if !reader.get_mut().take_auth_failed() {
+                    return Err(tracker.error("Failed to read archive")(e));
+                }
+                CheckStatus::Corrupted
+            }
+        };
+        report.entries += 1;
+        report.results.push(EntryCheck { path: entry.path.clone(), kind: entry.kind, status });
+        tracker.finish_entry();
+    }
+
+    // 条目读完后只剩结束标记，读取最后一块以确认文件没有被截断
+    let mut payload = reader.into_inner();
+    let tail = payload.plain_len().saturating_sub(1);
+    let result = payload.seek(SeekFrom::Start(tail)).and_then(|_| io::copy(&mut payload, &mut io::sink()));
+    if let Err(e) = result {
+        if !payload.take_auth_failed() {
+            return Err(tracker.error("Failed to read archive")(e));
         }
-        
-        // 删除临时 tar 文件
-        fs::remove_file(&temp_tar_path)
-            .map_err(|e| format!("Failed to delete temporary file: {}", e))?;
-        
-    } else if cfg!(target_os = "windows") {
-        // 在 Windows 上使用 7z
-        
-        let mut sevenzip_command = Command::new("7z");
-        sevenzip_command.arg("a").arg("-t7z").arg("-mhe=on");
-        
-        // 添加密码参数（如果有）
-        if !password.is_empty() {
-            let password_arg = format!("-p{}", password);
-            sevenzip_command.arg(password_arg);
+        report.complete = false;
+    }
+    Ok(report)
+}
+
+// 没有索引：只能顺序读取，某一块损坏后无法定位后面的条目，校验到此为止
+fn verify_sequential<R: Read + Seek>(payload: PayloadReader<R>, tracker: &Tracker) -> Result<VerifyReport, EncryptorError> {
+    let mut archive = tar::Archive::new(Counting::new(payload, tracker));
+    let mut report = VerifyReport { entries: 0, bytes: 0, results: Vec::new(), complete: true };
+    let result = (|| {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let kind = EntryKind::from_tar(entry.header().entry_type());
+            tracker.start_entry(&path)?;
+            report.results.push(EntryCheck { path, kind, status: CheckStatus::Corrupted });
+            report.bytes += io::copy(&mut entry, &mut io::sink())?;
+            // 读完内容才算通过
+            report.results.last_mut().unwrap().status = CheckStatus::Authenticated;
+            report.entries += 1;
+            tracker.finish_entry();
         }
-        
-        let folder_path_str = folder_path.to_string_lossy().to_string();
-        sevenzip_command.arg(&encrypted_file_path).arg(&folder_path_str);
-        
-        let output = sevenzip_command.output()
-            .map_err(|e| format!("Failed to execute 7z command: {}", e))?;
-        
-        if !output.status.success() {
-            return Err(format!("Failed to encrypt folder: {}", String::from_utf8_lossy(&output.stderr)));
+        Ok(())
+    })();
+
+    let mut payload = archive.into_inner().into_inner();
+    let result = result.and_then(|_| io::copy(&mut payload, &mut io::sink()).map(|_| ()));
+    if let Err(e) = result {
+        if !payload.take_auth_failed() {
+            return Err(tracker.error("Failed to read archive")(e));
         }
-    } else {
-        return Err("Unsupported operating system".to_string());
+        report.complete = false;
     }
-    
-    Ok(format!("Folder has been encrypted to: {}", encrypted_file_path))
+    Ok(report)
 }
 
-pub fn decrypt_folder(encrypted_file: &str, password: &str) -> Result<String, String> {
-    let encrypted_path = Path::new(encrypted_file);
-    
-    // 确保加密文件存在
-    if !encrypted_path.exists() {
-        return Err(format!("Encrypted file '{}' does not exist", encrypted_file));
+// 读取头部中的格式信息，不需要密码
+pub fn archive_info(encrypted_path: &Path) -> Result<ArchiveInfo, EncryptorError> {
+    if !encrypted_path.is_file() {
+        return Err(EncryptorError::SourceMissing(encrypted_path.to_path_buf()));
+    }
+    let input = File::open(encrypted_path)
+        .map_err(EncryptorError::io("Failed to open encrypted file"))?;
+    let size = input.metadata()
+        .map_err(EncryptorError::io("Failed to open encrypted file"))?
+        .len();
+
+    let mut reader = BufReader::new(input);
+    let kdf = |recipients: &[RecipientKind]| {
+        recipients.iter().find_map(|kind| match kind {
+            RecipientKind::Password(kdf) => Some(*kdf),
+            _ => None,
+        })
+    };
+    if age::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        let recipients = AgeHeader::read_from(&mut reader)?.recipients();
+        return Ok(ArchiveInfo {
+            format: ArchiveFormat::Age,
+            version: 1,
+            cipher: Cipher::ChaCha20Poly1305,
+            kdf: kdf(&recipients),
+            recipients,
+            indexed: false,
+            compression: Compression::None,
+            size,
+            methods: Vec::new(),
+        });
+    }
+
+    if sevenz::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        let summary = sevenz::inspect(reader)?;
+        let recipients = match summary.kdf {
+            Some(kdf) => vec![RecipientKind::Password(kdf)],
+            None => Vec::new(),
+        };
+        return Ok(ArchiveInfo {
+            format: ArchiveFormat::SevenZip,
+            version: summary.version,
+            cipher: if summary.encrypted { Cipher::Aes256Cbc } else { Cipher::None },
+            kdf: summary.kdf,
+            recipients,
+            indexed: true,
+            compression: Compression::None,
+            size,
+            methods: summary.methods,
+        });
     }
-    
+
+    if zip::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        let summary = zip::inspect(reader)?;
+        let (cipher, kdf) = if summary.encrypted { (Cipher::WinZipAes, Some(zip::KDF)) } else { (Cipher::None, None) };
+        return Ok(ArchiveInfo {
+            format: ArchiveFormat::Zip,
+            version: summary.version,
+            cipher,
+            kdf,
+            recipients: kdf.map(RecipientKind::Password).into_iter().collect(),
+            indexed: true,
+            compression: Compression::None,
+            size,
+            methods: summary.methods,
+        });
+    }
+
+    if openssl::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Ok(ArchiveInfo {
+            format: ArchiveFormat::OpenSsl,
+            version: 0,
+            cipher: Cipher::Aes256Cbc,
+            kdf: Some(openssl::KDF),
+            recipients: vec![RecipientKind::Password(openssl::KDF)],
+            indexed: false,
+            compression: Compression::None,
+            size,
+            methods: Vec::new(),
+        });
+    }
+
+    let (header, _, _) = Header::read_from(&mut reader)?;
+    let recipients: Vec<RecipientKind> = header.recipients.iter().map(|stanza| stanza.kind()).collect();
+    Ok(ArchiveInfo {
+        format: ArchiveFormat::Native,
+        version: header.version,
+        cipher: header.cipher,
+        kdf: kdf(&recipients),
+        recipients,
+        indexed: header.indexed,
+        compression: header.compression,
+        size,
+        methods: Vec::new(),
+    })
+}
+
+// 为已有归档添加接收者：用密码或私钥解开文件密钥后重写头部，载荷和索引原样复制，不重新加密。
+// 返回添加后的接收者总数
+pub fn add_recipients(encrypted_path: &Path, password: &str, options: &AddRecipientsOptions) -> Result<usize, EncryptorError> {
+    if options.recipients.is_empty() {
+        return Err(EncryptorError::InvalidOptions("no recipients to add".to_string()));
+    }
+    options.kdf.validate()?;
     if !encrypted_path.is_file() {
-        return Err(format!("'{}' is not a file", encrypted_file));
-    }
-    
-    // 获取文件所在目录和文件名
-    let parent_dir = encrypted_path.parent().unwrap_or(Path::new("."));
-    
-    // 创建输出目录名
-    let output_name = encrypted_path
-        .file_stem()
-        .ok_or("Cannot get file name")?
-        .to_string_lossy()
-        .to_string();
-    
-    // 解压目标目录（在加密文件同级目录下）
-    let output_dir = parent_dir.join(&output_name);
-    
-    if cfg!(target_os = "macos") || cfg!(target_os = "linux") {
-        // 在 macOS 或 Linux 上使用 openssl 和 tar
-        
-        // 临时 tar 文件名
-        let temp_tar = parent_dir.join(format!("{}.tar", output_name));
-        let temp_tar_path = temp_tar.to_string_lossy().to_string();
-        
-        // 使用 openssl 解密
-        let mut openssl_command = Command::new("openssl");
-        openssl_command
-            .arg("enc")
-            .arg("-aes-256-cbc")  // 使用 CBC 模式替代 GCM
-            .arg("-d")
-            .arg("-salt")
-            .arg("-pbkdf2")
-            .arg("-pass")
-            .arg("stdin")  // 从标准输入读取密码
-            .arg("-in")
-            .arg(encrypted_file)
-            .arg("-out")
-            .arg(&temp_tar_path)
-            .stdin(Stdio::piped()) // 设置标准输入
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
-        
-        // 启动命令并获取子进程
-        let mut child = openssl_command
-            .spawn()
-            .map_err(|e| format!("Failed to start openssl command: {}", e))?;
-        
-        // 将密码写入标准输入
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(password.as_bytes())
-                .map_err(|e| format!("Failed to write password: {}", e))?;
+        return Err(EncryptorError::SourceMissing(encrypted_path.to_path_buf()));
+    }
+    let input = File::open(encrypted_path).map_err(EncryptorError::io("Failed to open encrypted file"))?;
+    let mut reader = BufReader::new(input);
+    if age::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Err(EncryptorError::UnsupportedFormat(
+            "recipients cannot be added to age files; decrypt and encrypt again to add recipients".to_string(),
+        ));
+    }
+    if sevenz::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Err(EncryptorError::UnsupportedFormat(
+            "7z archives are protected by a single password; recipients cannot be added".to_string(),
+        ));
+    }
+    if zip::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Err(EncryptorError::UnsupportedFormat(
+            "zip archives are protected by a single password; recipients cannot be added".to_string(),
+        ));
+    }
+    if openssl::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Err(EncryptorError::UnsupportedFormat(
+            "archives encrypted with OpenSSL have a single password; upgrade them to the current format first".to_string(),
+        ));
+    }
+    let (mut header, header_bytes, expected_mac) = Header::read_from(&mut reader)?;
+    let (keys, file_key) = unlock(&header, &header_bytes, &expected_mac, password, &options.identities)?;
+    header.recipients.extend(recipient::wrap_all(&options.recipients, &file_key, &options.kdf)?);
+    let header_bytes = header.to_bytes();
+    let mut mac = header_mac(&keys);
+    mac.update(&header_bytes);
+
+    // 写到同目录的临时文件，完成后替换原文件
+    let output_dir = parent_dir(encrypted_path);
+    let temp_file = output::temp_path(output_dir);
+    let mut partial = PartialOutput::default();
+    partial.track(temp_file.clone());
+    let write_error = EncryptorError::io("Failed to write encrypted file");
+    let output = File::create(&temp_file).map_err(write_error)?;
+    let mut writer = BufWriter::new(output);
+    writer.write_all(&header_bytes)
+        .and_then(|_| writer.write_all(&mac.finalize().into_bytes()))
+        .and_then(|_| io::copy(&mut reader, &mut writer))
+        .and_then(|_| writer.into_inner().map_err(|e| e.into_error()))
+        .and_then(|file| file.sync_all())
+        .map_err(write_error)?;
+    output::persist(&temp_file, encrypted_path, ConflictPolicy::Overwrite)
+        .map_err(EncryptorError::io("Failed to move encrypted file into place"))?;
+    partial.commit();
+    Ok(header.recipients.len())
+}
+
+// 把归档解密后重新打包加密为 options.encrypt 指定的格式（默认为当前的 .aes 格式），条目内容和元数据不变，
+// 明文不落盘。用于转换旧版本生成的 OpenSSL 文件、7z、zip 归档或 age 文件。
+// 新归档加密给 password（不为空时）和 options.encrypt.recipients；原归档的公钥接收者无法从中得知，需要重新给出。
+// 未指定 destination 时替换原文件，指定的是已有文件夹时以原文件名保存到其中
+pub fn upgrade_archive(encrypted_path: &Path, password: &str, options: &UpgradeOptions) -> Result<EncryptReport, EncryptorError> {
+    let recipients = encrypt_recipients(password, &options.encrypt)?;
+    let opened = open_file(encrypted_path, password, &options.identities)?;
+
+    let mut encrypt = options.encrypt.clone();
+    let encrypted_file = match &encrypt.destination {
+        Some(destination) if destination.is_dir() => destination.join(archive_name(encrypted_path)?),
+        Some(destination) => destination.clone(),
+        None => {
+            encrypt.conflict = ConflictPolicy::Overwrite;
+            encrypted_path.to_path_buf()
+        }
+    };
+    if !parent_dir(&encrypted_file).is_dir() {
+        return Err(EncryptorError::SourceMissing(parent_dir(&encrypted_file).to_path_buf()));
+    }
+    output::resolve(&encrypted_file, encrypt.conflict)?;
+
+    // 进度按读取的原归档 tar 数据计算
+    let tracker = Tracker::new(encrypt.progress.as_ref(), encrypt.cancel.as_ref(), Phase::Archive);
+    tracker.set_totals(opened.entries.as_ref().map(|entries| entries.len()), Some(opened.payload.plain_len()));
+    let (encrypted_file, entries) = write_archive(&encrypted_file, &recipients, &encrypt, &tracker, |builder, entries| {
+        read_tar(opened.payload, &tracker, "Failed to convert archive", |archive| {
+            archive::copy_entries(archive, builder, !encrypt.skip_ownership, entries, &tracker)
+        })
+    })?;
+
+    Ok(EncryptReport {
+        output: encrypted_file,
+        entries: entries.len(),
+        bytes: entries.iter().map(|entry| entry.size).sum(),
+        skipped: Vec::new(),
+    })
+}
+
+fn open_file(encrypted_path: &Path, password: &str, identities: &[Identity]) -> Result<OpenedArchive<BufReader<File>>, EncryptorError> {
+    // 确保加密文件存在
+    if !encrypted_path.is_file() {
+        return Err(EncryptorError::SourceMissing(encrypted_path.to_path_buf()));
+    }
+    let input = File::open(encrypted_path)
+        .map_err(EncryptorError::io("Failed to open encrypted file"))?;
+    let mut reader = BufReader::new(input);
+    if age::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return open_age(reader, encrypted_path, password, identities);
+    }
+    if sevenz::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        let metadata = reader.get_ref().metadata().map_err(EncryptorError::io("Failed to read encrypted file"))?;
+        let archive = SevenZipReader::open(reader, password, &metadata)?;
+        let entries = archive.entries().to_vec();
+        return Ok(OpenedArchive { payload: PayloadReader::SevenZip(Box::new(archive)), entries: Some(entries) });
+    }
+    if zip::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        let metadata = reader.get_ref().metadata().map_err(EncryptorError::io("Failed to read encrypted file"))?;
+        let archive = ZipReader::open(reader, password, &metadata)?;
+        let entries = archive.entries().to_vec();
+        return Ok(OpenedArchive { payload: PayloadReader::Zip(Box::new(archive)), entries: Some(entries) });
+    }
+    if openssl::detect(&mut reader).map_err(EncryptorError::io("Failed to read encrypted file"))? {
+        return Ok(OpenedArchive { payload: PayloadReader::Legacy(CbcReader::open(reader, password)?), entries: None });
+    }
+    open_archive(reader, password, identities)
+}
+
+// age 文件的载荷是 tar 数据时按归档处理，否则视为单个文件，以去掉 ".age" 的文件名解压
+fn open_age(
+    mut reader: BufReader<File>,
+    encrypted_path: &Path,
+    password: &str,
+    identities: &[Identity],
+) -> Result<OpenedArchive<BufReader<File>>, EncryptorError> {
+    let header = AgeHeader::read_from(&mut reader)?;
+    let key = header.unlock(&mut reader, password, identities)?;
+
+    let read_error = EncryptorError::io("Failed to read encrypted file");
+    let metadata = reader.get_ref().metadata().map_err(read_error)?;
+    let payload_start = reader.stream_position().map_err(read_error)?;
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(read_error)?;
+    let mut payload = ChunkReader::age(reader, &key, payload_start, file_len);
+    let tar = match age::is_tar(&mut payload) {
+        Ok(tar) => tar,
+        Err(_) if payload.auth_failed() => return Err(EncryptorError::Corrupted),
+        Err(e) => return Err(read_error(e)),
+    };
+    if tar {
+        return Ok(OpenedArchive { payload: PayloadReader::Stream(payload), entries: None });
+    }
+
+    let file_name = archive_name(encrypted_path)?;
+    let name = match file_name.strip_suffix(".age") {
+        Some(stem) if !stem.is_empty() => stem.to_string(),
+        _ => format!("{}.decrypted", file_name),
+    };
+    Ok(OpenedArchive { payload: PayloadReader::Single(SingleFile::new(payload, &name, &metadata)), entries: None })
+}
+
+// 在解密后的 tar 流上执行 f。tar 读到结束标记就会停止，之后继续读完剩余数据，
+// 以校验最后一块的认证标签；认证失败统一报告为 Corrupted。
+fn read_tar<'t, 'a, R: Read + Seek, T>(
+    payload: PayloadReader<R>,
+    tracker: &'t Tracker<'a>,
+    context: &str,
+    f: impl FnOnce(&mut tar::Archive<Counting<'t, 'a, PayloadReader<R>>>) -> io::Result<T>,
+) -> Result<T, EncryptorError> {
+    let mut archive = tar::Archive::new(Counting::new(payload, tracker));
+    let result = f(&mut archive);
+
+    let mut payload = archive.into_inner().into_inner();
+    let result = result.and_then(|value| io::copy(&mut payload, &mut io::sink()).map(|_| value));
+    result.map_err(|e| {
+        if payload.auth_failed() {
+            EncryptorError::Corrupted
         } else {
-            return Err("Unable to get openssl standard input".to_string());
+            tracker.error(context)(e)
         }
-        
-        // 等待命令完成
-        let output = child.wait_with_output()
-            .map_err(|e| format!("Failed to wait for openssl command to complete: {}", e))?;
-        
-        if !output.status.success() {
-            // 获取错误输出
-            let stderr_output = String::from_utf8_lossy(&output.stderr);
-            
-            // 检查是否是密码错误导致的"bad decrypt"错误
-            if stderr_output.contains("bad decrypt") {
-                return Err("Decryption failed: Incorrect password".to_string());
-            }
-            
-            return Err(format!("Failed to decrypt file: {}", stderr_output));
+    })
+}
+
+fn not_found(pattern: &str) -> EncryptorError {
+    EncryptorError::InvalidOptions(format!("'{}' does not match any entry in the archive", pattern))
+}
+
+// 相对路径的父目录可能是空字符串，统一回退到当前目录
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+// 输入在归档中的名称。"."、".." 这类路径没有文件名，取其真实路径的最后一段
+fn archive_name(input: &Path) -> Result<String, EncryptorError> {
+    let name = match input.file_name() {
+        Some(name) => name.to_os_string(),
+        None => input
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.file_name().map(|name| name.to_os_string()))
+            .ok_or_else(|| EncryptorError::InvalidOptions(format!("cannot get a name for '{}'", input.display())))?,
+    };
+    Ok(name.to_string_lossy().into_owned())
+}
+
+// path 是否为 folder 本身或位于其中（按解析符号链接后的真实路径比较）
+fn is_inside(path: &Path, folder: &Path) -> bool {
+    match (path.canonicalize(), folder.canonicalize()) {
+        (Ok(path), Ok(folder)) => path.starts_with(folder),
+        _ => false,
+    }
+}
+
+struct Keys {
+    header: [u8; 32],
+    payload: Key,
+    index: Key,
+}
+
+// 用 HKDF 从随机文件密钥分出头部 MAC、载荷和索引各自的密钥
+fn derive_keys(master: &[u8]) -> Keys {
+    let hkdf = Hkdf::<Sha256>::new(None, master);
+    let mut keys = Keys { header: [0u8; 32], payload: Key::default(), index: Key::default() };
+    hkdf.expand(b"pw header mac", &mut keys.header).expect("valid HKDF output length");
+    hkdf.expand(b"pw payload", &mut keys.payload).expect("valid HKDF output length");
+    hkdf.expand(b"pw index", &mut keys.index).expect("valid HKDF output length");
+    keys
+}
+
+// 取得归档的密钥并校验头部 MAC：先用密码或私钥解开文件密钥，MAC 不匹配说明头部被改动。返回各密钥和文件密钥
+fn unlock(
+    header: &Header,
+    header_bytes: &[u8],
+    expected_mac: &[u8],
+    password: &str,
+    identities: &[Identity],
+) -> Result<(Keys, Vec<u8>), EncryptorError> {
+    let file_key = recipient::unwrap_any(&header.recipients, password, identities)?;
+    let keys = derive_keys(&file_key);
+    let mut mac = header_mac(&keys);
+    mac.update(header_bytes);
+    mac.verify_slice(expected_mac).map_err(|_| EncryptorError::Corrupted)?;
+    Ok((keys, file_key))
+}
+
+fn header_mac(keys: &Keys) -> HmacSha256 {
+    <HmacSha256 as Mac>::new_from_slice(&keys.header).expect("HMAC accepts any key length")
+}
+
+// 写归档时需要的头部和密钥
+enum Sealer {
+    Native {
+        aad: Vec<u8>,
+        payload_start: u64,
+        nonce_prefix: [u8; NONCE_PREFIX_LEN],
+        keys: Keys,
+    },
+    // age 文件只有载荷，没有索引
+    Age { key: Key },
+    // 7z 归档的头部在载荷之后，由 PayloadWriter 写出
+    SevenZip { key: [u8; 32], salt: [u8; 16], deflate: bool },
+    // zip 归档的每个条目使用各自的盐派生密钥，中央目录由 PayloadWriter 写出
+    Zip { password: String, deflate: bool },
+}
+
+impl Sealer {
+    // 输出格式：头部 + 头部 MAC + 分块载荷密文（每块以不含接收者的头部为附加认证数据）+ 索引密文 + footer
+    fn begin<W: Write>(writer: &mut W, recipients: &[Recipient], options: &EncryptOptions) -> Result<Sealer, EncryptorError> {
+        if options.format == ArchiveFormat::Age {
+            return Ok(Sealer::Age { key: age::write_header(writer, recipients, &options.kdf)? });
         }
-        
-        // 确保目标父目录存在
-        let parent_dir_str = parent_dir.to_string_lossy().to_string();
-        
-        // 使用tar解压，直接解压到父目录
-        // 不切换目录，使用-C选项指定解压目标目录
-        let tar_command = Command::new("tar")
-            .args(&[
-                "-xf", 
-                &temp_tar_path, 
-                "-C", 
-                &parent_dir_str
-            ])
-            .output()
-            .map_err(|e| format!("Failed to execute tar command: {}", e))?;
-        
-        if !tar_command.status.success() {
-            return Err(format!("Failed to extract file: {}", String::from_utf8_lossy(&tar_command.stderr)));
+        if options.format == ArchiveFormat::SevenZip {
+            let (key, salt) = sevenz::begin(writer, recipients)?;
+            return Ok(Sealer::SevenZip { key, salt, deflate: options.compression != Compression::None });
         }
-        
-        // 删除临时 tar 文件
-        fs::remove_file(&temp_tar_path)
-            .map_err(|e| format!("Failed to delete temporary file: {}", e))?;
-        
-    } else if cfg!(target_os = "windows") {
-        // 在 Windows 上使用 7z
-        
-        let mut sevenzip_command = Command::new("7z");
-        sevenzip_command.arg("x");
-        
-        // 添加密码参数（如果有）
-        if !password.is_empty() {
-            let password_arg = format!("-p{}", password);
-            sevenzip_command.arg(password_arg);
+        if options.format == ArchiveFormat::Zip {
+            let password = zip::begin(recipients)?;
+            return Ok(Sealer::Zip { password, deflate: options.compression != Compression::None });
         }
-        
-        // 指定输出目录为原始文件所在目录
-        let output_arg = format!("-o{}", parent_dir.display());
-        sevenzip_command.arg(encrypted_file).arg(output_arg);
-        
-        let output = sevenzip_command.output()
-            .map_err(|e| format!("Failed to execute 7z command: {}", e))?;
-        
-        if !output.status.success() {
-            let stderr_output = String::from_utf8_lossy(&output.stderr);
-            
-            // 检查是否是密码错误
-            if stderr_output.contains("Wrong password") {
-                return Err("Decryption failed: Incorrect password".to_string());
+
+        let mut file_key = [0u8; FILE_KEY_LEN];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        rand::thread_rng().fill_bytes(&mut file_key);
+        rand::thread_rng().fill_bytes(&mut nonce_prefix);
+
+        let header = Header {
+            version: FORMAT_VERSION,
+            cipher: Cipher::XChaCha20Poly1305,
+            nonce: nonce_prefix.to_vec(),
+            indexed: true,
+            compression: options.compression,
+            recipients: recipient::wrap_all(recipients, &file_key, &options.kdf)?,
+        };
+        let header_bytes = header.to_bytes();
+        let keys = derive_keys(&file_key);
+
+        let mut mac = header_mac(&keys);
+        mac.update(&header_bytes);
+
+        writer.write_all(&header_bytes)
+            .and_then(|_| writer.write_all(&mac.finalize().into_bytes()))
+            .map_err(EncryptorError::io("Failed to write encrypted file"))?;
+
+        let payload_start = (header_bytes.len() + MAC_LEN) as u64;
+        Ok(Sealer::Native { aad: header.aad(), payload_start, nonce_prefix, keys })
+    }
+
+    fn payload_writer<W: Write + Seek>(&self, writer: W) -> PayloadWriter<W> {
+        match self {
+            Sealer::Native { aad, nonce_prefix, keys, .. } => {
+                PayloadWriter::Chunked(EncryptWriter::new(writer, &keys.payload, *nonce_prefix, aad.clone()))
             }
-            
-            return Err(format!("Failed to decrypt folder: {}", stderr_output));
+            Sealer::Age { key } => PayloadWriter::Chunked(EncryptWriter::age(writer, key)),
+            Sealer::SevenZip { key, salt, deflate } => PayloadWriter::SevenZip(Box::new(sevenz::Writer::new(writer, *key, *salt, *deflate))),
+            Sealer::Zip { password, deflate } => PayloadWriter::Zip(Box::new(zip::Writer::new(writer, password.clone(), *deflate))),
         }
+    }
+
+    // 在载荷之后写入加密的索引（压缩时包括块表）和 footer
+    fn finish<W: Write + Seek>(&self, mut writer: W, entries: &[IndexEntry], blocks: Option<&BlockTable>) -> io::Result<W> {
+        let Sealer::Native { aad, payload_start, nonce_prefix, keys } = self else {
+            return Ok(writer);
+        };
+        let index_offset = writer.stream_position()?;
+        let mut index_writer = EncryptWriter::new(writer, &keys.index, *nonce_prefix, aad.clone());
+        index_writer.write_all(&index::encode(entries, blocks))?;
+
+        let mut writer = index_writer.finish()?;
+        // 相对载荷起点的偏移，添加接收者改变头部长度后仍然有效
+        writer.write_all(&(index_offset - payload_start).to_le_bytes())?;
+        writer.write_all(index::FOOTER_MAGIC)?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+// 载荷加密写入器：本工具和 age 格式为分块密文，7z、zip 归档解析 tar 流后写出各自的结构
+enum PayloadWriter<W: Write + Seek> {
+    Chunked(EncryptWriter<W>),
+    SevenZip(Box<sevenz::Writer<W>>),
+    Zip(Box<zip::Writer<W>>),
+}
+
+impl<W: Write + Seek> PayloadWriter<W> {
+    fn finish(self) -> io::Result<W> {
+        match self {
+            PayloadWriter::Chunked(writer) => writer.finish(),
+            PayloadWriter::SevenZip(writer) => writer.finish(),
+            PayloadWriter::Zip(writer) => writer.finish(),
+        }
+    }
+}
+
+impl<W: Write + Seek> Write for PayloadWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            PayloadWriter::Chunked(writer) => writer.write(buf),
+            PayloadWriter::SevenZip(writer) => writer.write(buf),
+            PayloadWriter::Zip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            PayloadWriter::Chunked(writer) => writer.flush(),
+            PayloadWriter::SevenZip(writer) => writer.flush(),
+            PayloadWriter::Zip(writer) => writer.flush(),
+        }
+    }
+}
+
+// 已通过密码校验的归档：载荷明文读取器和条目索引（age、OpenSSL 文件没有索引）
+struct OpenedArchive<R: Read + Seek> {
+    payload: PayloadReader<R>,
+    entries: Option<Vec<IndexEntry>>,
+}
+
+fn open_archive<R: Read + Seek>(mut reader: R, password: &str, identities: &[Identity]) -> Result<OpenedArchive<R>, EncryptorError> {
+    let (header, header_bytes, expected_mac) = Header::read_from(&mut reader)?;
+
+    // 头部 MAC 校验失败时不再尝试解密载荷
+    let (keys, _) = unlock(&header, &header_bytes, &expected_mac, password, identities)?;
+    if header.cipher != Cipher::XChaCha20Poly1305 {
+        return Err(EncryptorError::UnsupportedFormat(format!("{} is not used by this format", header.cipher)));
+    }
+    let aad = header.aad();
+
+    let read_error = EncryptorError::io("Failed to read encrypted file");
+    let payload_start = reader.stream_position().map_err(read_error)?;
+    let nonce_prefix: [u8; NONCE_PREFIX_LEN] = header.nonce.as_slice().try_into()
+        .map_err(|_| EncryptorError::UnsupportedFormat("unexpected nonce length".to_string()))?;
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(read_error)?;
+
+    // 分块密文，带索引时载荷之后是索引密文和 footer
+    let (payload_end, index) = if header.indexed {
+        let index_offset = index::read_footer(&mut reader, payload_start).map_err(|_| EncryptorError::Corrupted)?;
+        let mut index_reader = ChunkReader::new(
+            &mut reader, &keys.index, nonce_prefix, aad.clone(),
+            index_offset, file_len - index::FOOTER_LEN,
+        );
+        let mut data = Vec::new();
+        if let Err(e) = index_reader.read_to_end(&mut data) {
+            return Err(if index_reader.auth_failed() { EncryptorError::Corrupted } else { read_error(e) });
+        }
+        (index_offset, Some(index::decode(&data)?))
     } else {
-        return Err("Unsupported operating system".to_string());
+        (file_len, None)
+    };
+
+    let payload = ChunkReader::new(reader, &keys.payload, nonce_prefix, aad, payload_start, payload_end);
+    // 压缩的载荷需要索引中的块表才能定位各块
+    let (entries, blocks) = index.unzip();
+    let payload = match (header.compression, blocks.flatten()) {
+        (Compression::None, _) => PayloadReader::Stream(payload),
+        (_, Some(blocks)) => PayloadReader::Compressed(DecompressReader::new(payload, &blocks)),
+        (_, None) => return Err(EncryptorError::UnsupportedFormat("compressed archive has no block table".to_string())),
+    };
+    Ok(OpenedArchive { payload, entries })
+}
+
+// 解密后的载荷明文，按容器格式区分读取方式
+enum PayloadReader<R: Read + Seek> {
+    Stream(ChunkReader<R>),
+    Compressed(DecompressReader<ChunkReader<R>>),
+    // age 文件中的普通文件
+    Single(SingleFile<R>),
+    // OpenSSL 文件，没有认证
+    Legacy(CbcReader<R>),
+    // 7z 归档，以虚拟 tar 数据读取
+    SevenZip(Box<SevenZipReader<R>>),
+    // zip 归档，以虚拟 tar 数据读取
+    Zip(Box<ZipReader<R>>),
+}
+
+impl<R: Read + Seek> PayloadReader<R> {
+    fn auth_failed(&self) -> bool {
+        match self {
+            PayloadReader::Stream(reader) => reader.auth_failed(),
+            PayloadReader::Compressed(reader) => reader.get_ref().auth_failed(),
+            PayloadReader::Single(reader) => reader.get_ref().auth_failed(),
+            PayloadReader::Legacy(_) => false,
+            PayloadReader::SevenZip(reader) => reader.auth_failed(),
+            PayloadReader::Zip(reader) => reader.auth_failed(),
+        }
+    }
+
+    // 返回并清除认证失败标记，校验时一块损坏后继续读取其他块
+    fn take_auth_failed(&mut self) -> bool {
+        match self {
+            PayloadReader::Stream(reader) => reader.take_auth_failed(),
+            PayloadReader::Compressed(reader) => reader.get_mut().take_auth_failed(),
+            PayloadReader::Single(reader) => reader.get_mut().take_auth_failed(),
+            PayloadReader::Legacy(_) => false,
+            PayloadReader::SevenZip(reader) => reader.take_auth_failed(),
+            PayloadReader::Zip(reader) => reader.take_auth_failed(),
+        }
+    }
+
+    // 载荷明文总长度
+    fn plain_len(&self) -> u64 {
+        match self {
+            PayloadReader::Stream(reader) => reader.plain_len(),
+            PayloadReader::Compressed(reader) => reader.plain_len(),
+            PayloadReader::Single(reader) => reader.plain_len(),
+            PayloadReader::Legacy(reader) => reader.plain_len(),
+            PayloadReader::SevenZip(reader) => reader.plain_len(),
+            PayloadReader::Zip(reader) => reader.plain_len(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for PayloadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            PayloadReader::Stream(reader) => reader.read(buf),
+            PayloadReader::Compressed(reader) => reader.read(buf),
+            PayloadReader::Single(reader) => reader.read(buf),
+            PayloadReader::Legacy(reader) => reader.read(buf),
+            PayloadReader::SevenZip(reader) => reader.read(buf),
+            PayloadReader::Zip(reader) => reader.read(buf),
+        }
     }
-    
-    Ok(format!("File has been decrypted to: {}", output_dir.display()))
-} 
\ No newline at end of file
+}
+
+impl<R: Read + Seek> Seek for PayloadReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            PayloadReader::Stream(reader) => reader.seek(pos),
+            PayloadReader::Compressed(reader) => reader.seek(pos),
+            PayloadReader::Single(reader) => reader.seek(pos),
+            PayloadReader::Legacy(reader) => reader.seek(pos),
+            PayloadReader::SevenZip(reader) => reader.seek(pos),
+            PayloadReader::Zip(reader) => reader.seek(pos),
+        }
+    }
+}
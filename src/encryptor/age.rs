@@ -0,0 +1,515 @@
+// age 文件格式（age-encryption.org/v1），与 age、rage 命令行工具互通。
+//
+// ```text
+// age-encryption.org/v1
+// -> X25519 <临时公钥>
+// <包装后的文件密钥>
+// -> ssh-ed25519 <tag> <临时公钥>
+// <包装后的文件密钥>
+// --- <头部 MAC>
+// nonce（16 字节）+ 分块密文（见 `stream` 模块）
+// ```
+//
+// 参数和包装后的文件密钥均为无填充的 base64，文件密钥每行 64 字符，最后一行不足 64 字符（可以为空）。
+// 文件密钥为 16 字节，HKDF-SHA256 从中派生头部 MAC 的密钥（info "header"）和载荷密钥
+// （salt 为 nonce，info "payload"）。各接收者包装文件密钥的方式与本格式相同（见 `recipient` 模块），
+// 只是密码使用 scrypt，盐带有固定标签，且密码必须是唯一的接收者。无法识别的接收者类型（插件等）跳过。
+//
+// 写出的载荷是未压缩的 tar 数据，没有索引，`age -d` 之后可以直接用 tar 解开。
+// 别人用 age 加密的普通文件（载荷不是 tar 数据）按单个文件处理，见 `SingleFile`。
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use base64ct::{Base64Unpadded, Encoding};
+use chacha20poly1305::Key;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use super::error::EncryptorError;
+use super::kdf::Kdf;
+use super::recipient::{self, Identity, Recipient, RecipientKind, SALT_LEN, Stanza, TAG_LEN};
+use super::stream::ChunkReader;
+
+const MAGIC: &str = "age-encryption.org/v1";
+const MAC_PREFIX: &str = "---";
+const STANZA_PREFIX: &str = "-> ";
+const FILE_KEY_LEN: usize = 16;
+const NONCE_LEN: usize = 16;
+const COLUMNS: usize = 64;
+// 头部只有几行，超过这个长度不再读取
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+// 加密时的 scrypt 参数 N = 2^18，与 age 的默认值相同
+const SCRYPT_LOG_N: u8 = 18;
+
+const TAR_BLOCK: usize = 512;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// 文件开头是否为 age 头部；读取后回到原位置
+pub(super) fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let start = reader.stream_position()?;
+    let mut magic = Vec::new();
+    reader.by_ref().take(MAGIC.len() as u64 + 1).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(magic.strip_suffix(b"\n") == Some(MAGIC.as_bytes()))
+}
+
+// 写入头部和 nonce，返回载荷密钥。密码接收者使用 kdf（不是 scrypt 时使用 age 的默认参数）
+pub(super) fn write_header<W: Write>(writer: &mut W, recipients: &[Recipient], kdf: &Kdf) -> Result<Key, EncryptorError> {
+    if recipients.len() > 1 && recipients.iter().any(Recipient::is_password) {
+        return Err(EncryptorError::InvalidOptions(
+            "an age file protected by a password cannot have other recipients".to_string(),
+        ));
+    }
+    let scrypt = match kdf {
+        Kdf::Scrypt { .. } => *kdf,
+        _ => Kdf::Scrypt { log_n: SCRYPT_LOG_N },
+    };
+    let mut file_key = [0u8; FILE_KEY_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut file_key);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut header = format!("{}\n", MAGIC);
+    for recipient in recipients {
+        header.push_str(&encode_stanza(&recipient.wrap(&file_key, &scrypt, SCRYPT_LABEL)?));
+    }
+    header.push_str(MAC_PREFIX);
+    let mut mac = header_mac(&file_key);
+    mac.update(header.as_bytes());
+    header.push_str(&format!(" {}\n", Base64Unpadded::encode_string(&mac.finalize().into_bytes())));
+
+    writer.write_all(header.as_bytes())
+        .and_then(|_| writer.write_all(&nonce))
+        .map_err(EncryptorError::io("Failed to write encrypted file"))?;
+    Ok(payload_key(&file_key, &nonce))
+}
+
+fn encode_stanza(stanza: &Stanza) -> String {
+    let (args, body) = match stanza {
+        Stanza::Password { kdf: Kdf::Scrypt { log_n }, salt, body } => {
+            let salt = Base64Unpadded::encode_string(&salt[SCRYPT_LABEL.len()..]);
+            (format!("scrypt {} {}", salt, log_n), body)
+        }
+        Stanza::Password { .. } => unreachable!("age password recipients use scrypt"),
+        Stanza::X25519 { share, body } => (format!("X25519 {}", Base64Unpadded::encode_string(share)), body),
+        Stanza::SshEd25519 { tag, share, body } => (
+            format!("ssh-ed25519 {} {}", Base64Unpadded::encode_string(tag), Base64Unpadded::encode_string(share)),
+            body,
+        ),
+    };
+    let mut out = format!("{}{}\n", STANZA_PREFIX, args);
+    let encoded = Base64Unpadded::encode_string(body);
+    for line in encoded.as_bytes().chunks(COLUMNS) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    // 最后一行必须不足 64 字符，正好整除时补一个空行
+    if encoded.len() % COLUMNS == 0 {
+        out.push('\n');
+    }
+    out
+}
+
+// 解析后的头部
+pub(super) struct AgeHeader {
+    // 能识别的接收者
+    stanzas: Vec<Stanza>,
+    // MAC 覆盖的内容：从开头到 "---"
+    authenticated: Vec<u8>,
+    mac: Vec<u8>,
+}
+
+impl AgeHeader {
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<AgeHeader, EncryptorError> {
+        let mut raw = Vec::new();
+        if read_line(reader, &mut raw)? != MAGIC {
+            return Err(malformed());
+        }
+
+        let mut stanzas = Vec::new();
+        let mut password = false;
+        let mut count = 0;
+        loop {
+            let line = read_line(reader, &mut raw)?;
+            if let Some(mac) = line.strip_prefix(MAC_PREFIX) {
+                let mac = mac.strip_prefix(' ').ok_or_else(malformed)?;
+                let mac = Base64Unpadded::decode_vec(mac).map_err(|_| malformed())?;
+                let authenticated = raw[..raw.len() - line.len() - 1 + MAC_PREFIX.len()].to_vec();
+                // scrypt 接收者必须是唯一的接收者，防止把密码文件伪装成公钥加密的文件
+                if password && count > 1 {
+                    return Err(malformed());
+                }
+                return Ok(AgeHeader { stanzas, authenticated, mac });
+            }
+
+            let args: Vec<&str> = line.strip_prefix(STANZA_PREFIX).ok_or_else(malformed)?.split(' ').collect();
+            if args.iter().any(|arg| arg.is_empty()) {
+                return Err(malformed());
+            }
+            let mut encoded = String::new();
+            loop {
+                let body = read_line(reader, &mut raw)?;
+                if body.len() > COLUMNS {
+                    return Err(malformed());
+                }
+                encoded.push_str(&body);
+                if body.len() < COLUMNS {
+                    break;
+                }
+            }
+            let body = Base64Unpadded::decode_vec(&encoded).map_err(|_| malformed())?;
+            count += 1;
+            if let Some(stanza) = parse_stanza(&args, body)? {
+                password |= matches!(stanza, Stanza::Password { .. });
+                stanzas.push(stanza);
+            }
+        }
+    }
+
+    pub fn recipients(&self) -> Vec<RecipientKind> {
+        self.stanzas.iter().map(Stanza::kind).collect()
+    }
+
+    // 解开文件密钥并校验头部 MAC，再读取头部之后的 nonce，返回载荷密钥
+    pub fn unlock<R: Read>(&self, reader: &mut R, password: &str, identities: &[Identity]) -> Result<Key, EncryptorError> {
+        let file_key = recipient::unwrap_any(&self.stanzas, password, identities)?;
+        let mut mac = header_mac(&file_key);
+        mac.update(&self.authenticated);
+        mac.verify_slice(&self.mac).map_err(|_| EncryptorError::Corrupted)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        reader.read_exact(&mut nonce).map_err(|_| EncryptorError::Corrupted)?;
+        Ok(payload_key(&file_key, &nonce))
+    }
+}
+
+fn malformed() -> EncryptorError {
+    EncryptorError::UnsupportedFormat("malformed age header".to_string())
+}
+
+// 读取一行（不含换行符），原始字节追加到 raw
+fn read_line<R: Read>(reader: &mut R, raw: &mut Vec<u8>) -> Result<String, EncryptorError> {
+    let start = raw.len();
+    loop {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte).map_err(|_| malformed())?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        if !(byte[0] == b' ' || byte[0].is_ascii_graphic()) || raw.len() >= MAX_HEADER_LEN {
+            return Err(malformed());
+        }
+        raw.push(byte[0]);
+    }
+    let line = String::from_utf8(raw[start..].to_vec()).map_err(|_| malformed())?;
+    raw.push(b'\n');
+    Ok(line)
+}
+
+// 无法识别的类型返回 None
+fn parse_stanza(args: &[&str], body: Vec<u8>) -> Result<Option<Stanza>, EncryptorError> {
+    let decode = |arg: &str, len: usize| {
+        Base64Unpadded::decode_vec(arg).ok().filter(|value| value.len() == len).ok_or_else(malformed)
+    };
+    let stanza = match args {
+        ["X25519", share] => Stanza::X25519 { share: decode(share, 32)?.try_into().unwrap(), body },
+        ["ssh-ed25519", tag, share] => Stanza::SshEd25519 {
+            tag: decode(tag, 4)?.try_into().unwrap(),
+            share: decode(share, 32)?.try_into().unwrap(),
+            body,
+        },
+        ["scrypt", salt, log_n] => {
+            // 工作因子为不带前导零的十进制数
+            if log_n.starts_with('0') || !log_n.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(malformed());
+            }
+            let kdf = Kdf::Scrypt { log_n: log_n.parse().map_err(|_| malformed())? };
+            // 过大的工作因子会耗尽内存，拒绝解密
+            kdf.validate()
+                .map_err(|_| EncryptorError::UnsupportedFormat(format!("scrypt work factor {} is too large", log_n)))?;
+            let salt = [SCRYPT_LABEL, &decode(salt, SALT_LEN)?].concat();
+            Stanza::Password { kdf, salt, body }
+        }
+        _ => return Ok(None),
+    };
+    if stanza_body(&stanza).len() != FILE_KEY_LEN + TAG_LEN {
+        return Err(malformed());
+    }
+    Ok(Some(stanza))
+}
+
+fn stanza_body(stanza: &Stanza) -> &[u8] {
+    match stanza {
+        Stanza::Password { body, .. } | Stanza::X25519 { body, .. } | Stanza::SshEd25519 { body, .. } => body,
+    }
+}
+
+fn header_mac(file_key: &[u8]) -> HmacSha256 {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, file_key).expand(b"header", &mut key).expect("valid HKDF output length");
+    <HmacSha256 as Mac>::new_from_slice(&key).expect("HMAC accepts any key length")
+}
+
+fn payload_key(file_key: &[u8], nonce: &[u8; NONCE_LEN]) -> Key {
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(nonce), file_key).expand(b"payload", &mut key).expect("valid HKDF output length");
+    key
+}
+
+// 载荷开头是否为 tar 头（ustar 或 GNU 格式）；读取后回到开头
+pub(super) fn is_tar<R: Read + Seek>(payload: &mut ChunkReader<R>) -> io::Result<bool> {
+    if payload.plain_len() < TAR_BLOCK as u64 {
+        return Ok(false);
+    }
+    let mut block = [0u8; TAR_BLOCK];
+    payload.seek(SeekFrom::Start(0))?;
+    payload.read_exact(&mut block)?;
+    payload.seek(SeekFrom::Start(0))?;
+    Ok(&block[257..262] == b"ustar")
+}
+
+// 把不是 tar 的载荷包装成只含这一个文件的 tar 数据，解压、列目录和校验不必区分。
+// 修改时间和属主取自 age 文件本身
+pub(super) struct SingleFile<R: Read + Seek> {
+    header: Vec<u8>,
+    inner: ChunkReader<R>,
+    len: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> SingleFile<R> {
+    pub fn new(inner: ChunkReader<R>, name: &str, metadata: &fs::Metadata) -> Self {
+        let len = inner.plain_len();
+        let mut header = tar::Header::new_ustar();
+        // 名称过长时改用固定名称
+        if header.set_path(name).is_err() {
+            header.set_path("decrypted").expect("short relative path");
+        }
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(len);
+        header.set_mode(0o644);
+        if let Ok(modified) = metadata.modified()
+            && let Ok(since_epoch) = modified.duration_since(std::time::UNIX_EPOCH)
+        {
+            header.set_mtime(since_epoch.as_secs());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            header.set_uid(metadata.uid() as u64);
+            header.set_gid(metadata.gid() as u64);
+        }
+        header.set_cksum();
+        SingleFile { header: header.as_bytes().to_vec(), inner, len, position: 0 }
+    }
+
+    pub fn get_ref(&self) -> &ChunkReader<R> {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut ChunkReader<R> {
+        &mut self.inner
+    }
+
+    // tar 头 + 内容（补齐到 512 字节）+ 两个全零的结束块
+    pub fn plain_len(&self) -> u64 {
+        TAR_BLOCK as u64 + self.len.next_multiple_of(TAR_BLOCK as u64) + 2 * TAR_BLOCK as u64
+    }
+}
+
+impl<R: Read + Seek> Read for SingleFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let header_len = TAR_BLOCK as u64;
+        let take = if self.position < header_len {
+            let available = &self.header[self.position as usize..];
+            let take = available.len().min(buf.len());
+            buf[..take].copy_from_slice(&available[..take]);
+            take
+        } else if self.position < header_len + self.len {
+            let remaining = header_len + self.len - self.position;
+            let limit = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            self.inner.seek(SeekFrom::Start(self.position - header_len))?;
+            self.inner.read(&mut buf[..limit])?
+        } else {
+            let remaining = self.plain_len().saturating_sub(self.position);
+            let take = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            buf[..take].fill(0);
+            take
+        };
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for SingleFile<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use sha2::Digest;
+
+    use super::*;
+    use crate::encryptor::stream::EncryptWriter;
+    use crate::encryptor::testutil::{fixture, hex};
+
+    // C2SP age 测试集（testkit，由 age 的参考实现生成）中的一个样本：
+    // 文本说明预期结果和密钥，空行之后是 age 文件本身
+    struct Vector {
+        expect: String,
+        payload: Option<Vec<u8>>,
+        passphrase: String,
+        identities: Vec<Identity>,
+        file: Vec<u8>,
+    }
+
+    fn load(name: &str) -> Vector {
+        let data = fixture(&format!("age/testkit/{}", name));
+        let split = data.windows(2).position(|pair| pair == b"\n\n").unwrap();
+        let mut vector = Vector {
+            expect: String::new(),
+            payload: None,
+            passphrase: String::new(),
+            identities: Vec::new(),
+            file: data[split + 2..].to_vec(),
+        };
+        for line in std::str::from_utf8(&data[..split]).unwrap().lines() {
+            let (key, value) = line.split_once(": ").unwrap();
+            match key {
+                "expect" => vector.expect = value.to_string(),
+                "payload" => vector.payload = Some(hex(value)),
+                "passphrase" => vector.passphrase = value.to_string(),
+                "identity" => vector.identities.push(Identity::parse(value).unwrap()),
+                _ => {}
+            }
+        }
+        vector
+    }
+
+    // 按 testkit 的分类返回失败的阶段
+    fn decrypt(vector: &Vector) -> Result<Vec<u8>, &'static str> {
+        let mut reader = Cursor::new(vector.file.clone());
+        let header = AgeHeader::read_from(&mut reader).map_err(|_| "header failure")?;
+        let key = header.unlock(&mut reader, &vector.passphrase, &vector.identities).map_err(|e| match e {
+            EncryptorError::NoMatchingKey | EncryptorError::WrongPassword => "no match",
+            EncryptorError::Corrupted => "HMAC failure",
+            _ => "header failure",
+        })?;
+        let start = reader.position();
+        let end = vector.file.len() as u64;
+        let mut plaintext = Vec::new();
+        ChunkReader::age(reader, &key, start, end).read_to_end(&mut plaintext).map_err(|_| "payload failure")?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn testkit_vectors() {
+        let mut names: Vec<String> = fs::read_dir(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/age/testkit"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        let mut failures = Vec::new();
+        for name in &names {
+            let vector = load(name);
+            let outcome = decrypt(&vector);
+            let ok = match (&outcome, vector.expect.as_str()) {
+                (Ok(plaintext), "success") => vector.payload.as_deref() == Some(&Sha256::digest(plaintext)[..]),
+                // 头部之后的 nonce 缺失，或低阶点等情况可能在解开文件密钥时才发现
+                (Err("HMAC failure" | "no match"), "header failure") => true,
+                (Err(stage), expect) => *stage == expect,
+                _ => false,
+            };
+            if !ok {
+                failures.push(format!("{}: expected {}, got {:?}", name, vector.expect, outcome.map(|p| p.len())));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    fn encrypt(recipients: &[Recipient], data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let key = write_header(&mut file, recipients, &Kdf::Scrypt { log_n: 10 }).unwrap();
+        let mut writer = EncryptWriter::age(file, &key);
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn open(file: &[u8], password: &str, identities: &[Identity]) -> Result<Vec<u8>, EncryptorError> {
+        let mut reader = Cursor::new(file);
+        let key = AgeHeader::read_from(&mut reader)?.unlock(&mut reader, password, identities)?;
+        let start = reader.position();
+        let mut plaintext = Vec::new();
+        ChunkReader::age(reader, &key, start, file.len() as u64)
+            .read_to_end(&mut plaintext)
+            .map_err(|_| EncryptorError::Corrupted)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = vec![42u8; 100_000];
+        let identity = Identity::generate();
+        let file = encrypt(&[identity.recipient(), Identity::generate().recipient()], &data);
+        assert!(file.starts_with(b"age-encryption.org/v1\n-> X25519 "));
+        assert_eq!(open(&file, "", &[identity]).unwrap(), data);
+        assert!(matches!(open(&file, "", &[Identity::generate()]), Err(EncryptorError::NoMatchingKey)));
+
+        let file = encrypt(&[Recipient::password("secret")], &data);
+        assert!(file.starts_with(b"age-encryption.org/v1\n-> scrypt "));
+        assert_eq!(open(&file, "secret", &[]).unwrap(), data);
+        assert!(matches!(open(&file, "wrong", &[]), Err(EncryptorError::WrongPassword)));
+
+        // 密码必须是唯一的接收者
+        let mixed = [Recipient::password("secret"), Identity::generate().recipient()];
+        assert!(write_header(&mut Vec::new(), &mixed, &Kdf::Scrypt { log_n: 10 }).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_header_mac() {
+        let identity = Identity::generate();
+        let mut file = encrypt(&[identity.recipient()], b"hello");
+        let mac = file.windows(4).position(|window| window == b"--- ").unwrap() + 4;
+        file[mac] = if file[mac] == b'A' { b'B' } else { b'A' };
+        assert!(matches!(open(&file, "", &[identity]), Err(EncryptorError::Corrupted)));
+    }
+
+    #[test]
+    fn rejects_malformed_stanzas() {
+        let identity = Identity::generate();
+        let file = String::from_utf8_lossy(&encrypt(&[identity.recipient()], b"")).into_owned();
+        let (header, _) = file.split_once("\n---").unwrap();
+        let (share_line, body) = header.strip_prefix("age-encryption.org/v1\n").unwrap().split_once('\n').unwrap();
+        let cases = [
+            // 参数为空、缺少正文、正文超过一行 64 字符、base64 带填充
+            format!("{}  \n{}", share_line, body),
+            share_line.to_string(),
+            format!("{}\n{}{}", share_line, body, "A".repeat(64)),
+            format!("{}\n{}=", share_line, body),
+            // 临时公钥长度不对
+            format!("-> X25519 AAAA\n{}", body),
+        ];
+        for stanza in cases {
+            let header = format!("age-encryption.org/v1\n{}\n--- AAAA\n", stanza);
+            let result = AgeHeader::read_from(&mut Cursor::new(header.as_bytes()));
+            assert!(matches!(result, Err(EncryptorError::UnsupportedFormat(_))), "{:?} should be rejected", stanza);
+        }
+    }
+}
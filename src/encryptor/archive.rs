@@ -0,0 +1,210 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use super::compress::{self, CompressWriter};
+use super::index::{EntryKind, IndexEntry};
+use super::metadata;
+use super::progress::{Counting, Tracker};
+
+fn entry_kind(file_type: fs::FileType) -> EntryKind {
+    if file_type.is_file() {
+        EntryKind::File
+    } else if file_type.is_dir() {
+        EntryKind::Directory
+    } else if file_type.is_symlink() {
+        EntryKind::Symlink
+    } else {
+        EntryKind::Other
+    }
+}
+
+// 套接字只在运行时有意义，tar 也无法记录，打包时跳过
+#[cfg(unix)]
+fn is_socket(file_type: fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    file_type.is_socket()
+}
+
+#[cfg(not(unix))]
+fn is_socket(_file_type: fs::FileType) -> bool {
+    false
+}
+
+// 打包前遍历一遍，统计条目数和文件总字节数，用于计算进度
+pub fn scan(source: &Path, tracker: &Tracker) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if is_socket(metadata.file_type()) {
+        return Ok(());
+    }
+    let kind = entry_kind(metadata.file_type());
+    tracker.scanned(if kind == EntryKind::File { metadata.len() } else { 0 })?;
+
+    if kind == EntryKind::Directory {
+        for child in fs::read_dir(source)? {
+            scan(&child?.path(), tracker)?;
+        }
+    }
+    Ok(())
+}
+
+// 递归打包 source，条目名以 name 为前缀；同时记录每个条目在 tar 数据中的偏移。
+// 子条目按名称排序，保证同一目录多次打包的结果一致。文件内容读取的字节数计入进度。
+// ownership 为 false 时 uid/gid 记为 0，不暴露本机的用户和组。跳过的套接字把条目名记入 skipped。
+pub fn append_tree<W: Write>(
+    builder: &mut tar::Builder<CompressWriter<W>>,
+    source: &Path,
+    name: &str,
+    ownership: bool,
+    entries: &mut Vec<IndexEntry>,
+    skipped: &mut Vec<String>,
+    tracker: &Tracker,
+) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if is_socket(metadata.file_type()) {
+        skipped.push(name.to_string());
+        return Ok(());
+    }
+    let kind = entry_kind(metadata.file_type());
+    tracker.start_entry(name)?;
+
+    // 索引中的修改时间和权限取自同样的 tar 头，与解压结果一致
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(&metadata, tar::HeaderMode::Complete);
+    if !ownership {
+        header.set_uid(0);
+        header.set_gid(0);
+    }
+
+    // 偏移指向 PAX 扩展头，读取时 tar 会把它和后面的条目一起解析
+    let offset = builder.get_ref().position();
+    if kind != EntryKind::Other {
+        metadata::append_pax(builder, &metadata::pax_records(source, &metadata)?)?;
+    }
+    let mut sha256 = None;
+    if kind == EntryKind::File {
+        // 按打开时的大小读取，打包过程中文件变长也不会破坏 tar 结构
+        let file = File::open(source)?;
+        if compress::is_precompressed(source) {
+            builder.get_mut().mark_incompressible(metadata.len());
+        }
+        let mut reader = Hashing::new(Counting::new(file.take(metadata.len()), tracker));
+        builder.append_data(&mut header, name, &mut reader)?;
+        sha256 = Some(reader.finish());
+    } else if kind == EntryKind::Directory {
+        builder.append_data(&mut header, name, io::empty())?;
+    } else if kind == EntryKind::Symlink {
+        builder.append_link(&mut header, name, fs::read_link(source)?)?;
+    } else {
+        builder.append_path_with_name(source, name)?;
+    }
+    entries.push(IndexEntry {
+        path: name.to_string(),
+        kind,
+        size: if kind == EntryKind::File { metadata.len() } else { 0 },
+        offset,
+        mtime: header.mtime().ok(),
+        // 只保留权限位，去掉文件类型位
+        mode: header.mode().ok().map(|mode| mode & 0o7777),
+        sha256,
+    });
+    tracker.finish_entry();
+
+    if kind == EntryKind::Directory {
+        let mut children = fs::read_dir(source)?.collect::<io::Result<Vec<_>>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+            append_tree(builder, &child.path(), &child_name, ownership, entries, skipped, tracker)?;
+        }
+    }
+    Ok(())
+}
+
+// 把已有归档中的条目逐个复制到 builder，tar 头和 PAX 扩展记录（修改时间、扩展属性等）保持不变，
+// 同时重新计算文件哈希、记录偏移。ownership 为 false 时属主记为 0。
+pub fn copy_entries<R: Read, W: Write>(
+    archive: &mut tar::Archive<R>,
+    builder: &mut tar::Builder<CompressWriter<W>>,
+    ownership: bool,
+    entries: &mut Vec<IndexEntry>,
+    tracker: &Tracker,
+) -> io::Result<()> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        // 全局 PAX 头不是条目，只含注释、字符集等信息
+        if entry_type.is_pax_global_extensions() {
+            continue;
+        }
+        let kind = EntryKind::from_tar(entry_type);
+        let path = entry.path()?.to_string_lossy().trim_end_matches('/').to_string();
+        tracker.start_entry(&path)?;
+
+        let mut header = entry.header().clone();
+        if !ownership {
+            header.set_uid(0);
+            header.set_gid(0);
+            // 很老的 tar 头没有用户名和组名字段
+            let _ = header.set_username("");
+            let _ = header.set_groupname("");
+        }
+        let size = if kind == EntryKind::File { entry.size() } else { 0 };
+        header.set_size(size);
+
+        let offset = builder.get_ref().position();
+        metadata::append_pax(builder, &metadata::copy_pax(&mut entry, ownership)?)?;
+        let mut sha256 = None;
+        if let Some(target) = entry.link_name()? {
+            let target = target.into_owned();
+            builder.append_link(&mut header, &path, target)?;
+        } else if kind == EntryKind::File {
+            if compress::is_precompressed(Path::new(&path)) {
+                builder.get_mut().mark_incompressible(size);
+            }
+            let mut reader = Hashing::new(&mut entry);
+            builder.append_data(&mut header, &path, &mut reader)?;
+            sha256 = Some(reader.finish());
+        } else {
+            builder.append_data(&mut header, &path, io::empty())?;
+        }
+        entries.push(IndexEntry {
+            path,
+            kind,
+            size,
+            offset,
+            mtime: header.mtime().ok(),
+            mode: header.mode().ok().map(|mode| mode & 0o7777),
+            sha256,
+        });
+        tracker.finish_entry();
+    }
+    Ok(())
+}
+
+// 读取时计算内容的 SHA-256，写入索引供校验时比对
+pub(super) struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> Hashing<R> {
+    pub fn new(inner: R) -> Self {
+        Hashing { inner, hasher: Sha256::new() }
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// 取消令牌。克隆后交给工作线程，任意一方调用 cancel 后，
+// 引擎会在处理下一块数据或下一个条目前停止，并返回 EncryptorError::Cancelled。
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// 任务失败或取消时删除已写出的部分输出。只登记本次任务新建的临时文件或目录，
+// 成功后调用 commit 保留；否则在 drop 时按登记的逆序连同内容一起删除。
+#[derive(Default)]
+pub(super) struct PartialOutput {
+    paths: Vec<PathBuf>,
+    committed: bool,
+}
+
+impl PartialOutput {
+    pub fn track(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        for path in self.paths.iter().rev() {
+            let Ok(metadata) = fs::symlink_metadata(path) else {
+                continue;
+            };
+            // 清理失败不影响返回原来的错误
+            let _ = if metadata.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        }
+    }
+}
@@ -0,0 +1,471 @@
+// 压缩层：位于 tar 打包和分块加密之间。
+//
+// tar 数据按 BLOCK_SIZE 切块，每块单独压缩为一帧：
+//
+// ```text
+// method  1 字节   0 = 原样存储，1 = zstd，2 = lz4
+// len     4 字节   帧数据长度，u32 LE
+// data    len 字节
+// ```
+//
+// 除最后一块外每块解压后正好是 BLOCK_SIZE 字节。各帧的长度记录在条目索引中（块表），
+// 读取时据此定位到任意块，列目录、选择性解压和逐条校验都不必从头解压。
+// 压缩后不变小的块，以及大部分内容来自已压缩文件（jpg、mp4、zip 等）的块原样存储。
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use super::error::EncryptorError;
+
+pub const BLOCK_SIZE: usize = 128 * 1024;
+const FRAME_HEADER_LEN: usize = 5;
+
+const METHOD_STORED: u8 = 0;
+const METHOD_ZSTD: u8 = 1;
+const METHOD_LZ4: u8 = 2;
+
+// zstd 压缩级别范围；级别越高越慢，压缩率越高
+pub const ZSTD_LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+// 加密前的压缩算法，记录在头部
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    // 不压缩，载荷就是 tar 数据
+    None,
+    Zstd { level: i32 },
+    // 速度优先，没有级别可选
+    Lz4,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd { level: 3 }
+    }
+}
+
+impl Compression {
+    pub(super) fn validate(&self) -> Result<(), EncryptorError> {
+        match self {
+            Compression::Zstd { level } if !ZSTD_LEVELS.contains(level) => Err(EncryptorError::InvalidOptions(format!(
+                "zstd level must be between {} and {}",
+                ZSTD_LEVELS.start(),
+                ZSTD_LEVELS.end()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // 头部字段的值：算法 id，zstd 另加级别（i32 LE，仅供显示）
+    pub(super) fn to_field(self) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => None,
+            Compression::Zstd { level } => Some([&[METHOD_ZSTD][..], &level.to_le_bytes()].concat()),
+            Compression::Lz4 => Some(vec![METHOD_LZ4]),
+        }
+    }
+
+    pub(super) fn from_field(value: &[u8]) -> Result<Self, EncryptorError> {
+        match value {
+            [METHOD_ZSTD, level @ ..] => {
+                let level = level.try_into().map(i32::from_le_bytes).unwrap_or(0);
+                Ok(Compression::Zstd { level })
+            }
+            [METHOD_LZ4] => Ok(Compression::Lz4),
+            _ => Err(EncryptorError::UnsupportedFormat("unknown compression".to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Zstd { level } => write!(f, "zstd level {}", level),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+// 按扩展名判断内容是否已经压缩过，这类文件再压缩只会浪费时间
+pub fn is_precompressed(path: &Path) -> bool {
+    const EXTENSIONS: &[&str] = &[
+        "jpg", "jpeg", "png", "gif", "webp", "heic", "avif", "mp4", "m4v", "mkv", "mov", "avi", "webm", "mp3", "m4a", "aac",
+        "ogg", "opus", "flac", "zip", "7z", "rar", "gz", "tgz", "bz2", "xz", "zst", "lz4", "docx", "xlsx", "pptx", "odt",
+        "ods", "odp", "epub", "jar", "apk", "aes",
+    ];
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.iter().any(|known| extension.eq_ignore_ascii_case(known)))
+}
+
+// 块表：每帧的长度（含帧头）和解压后的总长度
+#[derive(Clone, Debug, Default)]
+pub struct BlockTable {
+    pub frames: Vec<u32>,
+    pub plain_len: u64,
+}
+
+pub struct CompressWriter<W: Write> {
+    inner: W,
+    compression: Compression,
+    buffer: Vec<u8>,
+    table: BlockTable,
+    // 已压缩文件内容在 tar 数据中的范围，落在其中的块不尝试压缩
+    incompressible: Range<u64>,
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn new(inner: W, compression: Compression) -> Self {
+        Self {
+            inner,
+            compression,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            table: BlockTable::default(),
+            incompressible: 0..0,
+        }
+    }
+
+    // 已写入的未压缩字节数，用于记录条目在 tar 数据中的位置
+    pub fn position(&self) -> u64 {
+        self.table.plain_len + self.buffer.len() as u64
+    }
+
+    // 接下来的 len 字节来自已压缩的文件
+    pub fn mark_incompressible(&mut self, len: u64) {
+        let start = self.position();
+        self.incompressible = start..start + len;
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let start = self.table.plain_len;
+        let end = start + self.buffer.len() as u64;
+        let skipped = end.min(self.incompressible.end).saturating_sub(start.max(self.incompressible.start));
+
+        let compressed = if skipped * 2 >= self.buffer.len() as u64 {
+            None
+        } else {
+            match self.compression {
+                Compression::None => None,
+                Compression::Zstd { level } => Some((METHOD_ZSTD, zstd::bulk::compress(&self.buffer, level)?)),
+                Compression::Lz4 => Some((METHOD_LZ4, lz4_flex::block::compress(&self.buffer))),
+            }
+        };
+        let (method, data) = match &compressed {
+            Some((method, data)) if data.len() < self.buffer.len() => (*method, data.as_slice()),
+            _ => (METHOD_STORED, self.buffer.as_slice()),
+        };
+
+        self.inner.write_all(&[method])?;
+        self.inner.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.inner.write_all(data)?;
+        self.table.frames.push((FRAME_HEADER_LEN + data.len()) as u32);
+        self.table.plain_len = end;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // 写出最后一块，返回底层 writer 和块表；不压缩时没有块表
+    pub fn finish(mut self) -> io::Result<(W, Option<BlockTable>)> {
+        if self.compression == Compression::None {
+            return Ok((self.inner, None));
+        }
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        Ok((self.inner, Some(self.table)))
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.compression == Compression::None {
+            let n = self.inner.write(data)?;
+            self.table.plain_len += n as u64;
+            return Ok(n);
+        }
+        let take = data.len().min(BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..take]);
+        if self.buffer.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 可随机访问的解压读取器，只解压实际读到的块
+pub struct DecompressReader<R: Read + Seek> {
+    inner: R,
+    // 每帧在压缩数据中的起始偏移
+    offsets: Vec<u64>,
+    plain_len: u64,
+    loaded: Option<usize>,
+    block: Vec<u8>,
+    position: u64,
+}
+
+impl<R: Read + Seek> DecompressReader<R> {
+    pub fn new(inner: R, table: &BlockTable) -> Self {
+        let offsets = table
+            .frames
+            .iter()
+            .scan(0u64, |offset, &len| {
+                let start = *offset;
+                *offset += len as u64;
+                Some(start)
+            })
+            .collect();
+        Self { inner, offsets, plain_len: table.plain_len, loaded: None, block: Vec::new(), position: 0 }
+    }
+
+    pub fn plain_len(&self) -> u64 {
+        self.plain_len
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    fn load_block(&mut self, index: usize) -> io::Result<()> {
+        if self.loaded == Some(index) {
+            return Ok(());
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "compressed block is malformed");
+        let offset = *self.offsets.get(index).ok_or_else(invalid)?;
+        let expected = (self.plain_len - index as u64 * BLOCK_SIZE as u64).min(BLOCK_SIZE as u64) as usize;
+
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let mut frame_header = [0u8; FRAME_HEADER_LEN];
+        self.inner.read_exact(&mut frame_header)?;
+        let len = u32::from_le_bytes(frame_header[1..].try_into().unwrap()) as usize;
+        // 帧数据不会比原始块更长，超出说明数据有误，避免按错误长度分配内存
+        if len > BLOCK_SIZE {
+            return Err(invalid());
+        }
+        let mut data = vec![0u8; len];
+        self.inner.read_exact(&mut data)?;
+
+        self.loaded = None;
+        self.block = match frame_header[0] {
+            METHOD_STORED => data,
+            METHOD_ZSTD => zstd::bulk::decompress(&data, expected).map_err(|_| invalid())?,
+            METHOD_LZ4 => {
+                let mut block = vec![0u8; expected];
+                let n = lz4_flex::block::decompress_into(&data, &mut block).map_err(|_| invalid())?;
+                block.truncate(n);
+                block
+            }
+            _ => return Err(invalid()),
+        };
+        if self.block.len() != expected {
+            return Err(invalid());
+        }
+        self.loaded = Some(index);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.plain_len {
+            return Ok(0);
+        }
+        let index = (self.position / BLOCK_SIZE as u64) as usize;
+        self.load_block(index)?;
+
+        let offset = (self.position % BLOCK_SIZE as u64) as usize;
+        let available = &self.block[offset..];
+        let take = available.len().min(buf.len());
+        buf[..take].copy_from_slice(&available[..take]);
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecompressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // 两块半可压缩的数据
+    fn text() -> Vec<u8> {
+        let line = b"the quick brown fox jumps over the lazy dog\n";
+        line.iter().copied().cycle().take(BLOCK_SIZE * 5 / 2).collect()
+    }
+
+    // xorshift 生成的随机字节，压缩不了
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn compress(data: &[u8], compression: Compression) -> (Vec<u8>, BlockTable) {
+        let mut writer = CompressWriter::new(Vec::new(), compression);
+        writer.write_all(data).unwrap();
+        let (packed, table) = writer.finish().unwrap();
+        (packed, table.unwrap())
+    }
+
+    // 各帧的压缩方式
+    fn methods(packed: &[u8], table: &BlockTable) -> Vec<u8> {
+        let mut offset = 0;
+        table
+            .frames
+            .iter()
+            .map(|&len| {
+                let method = packed[offset];
+                offset += len as usize;
+                method
+            })
+            .collect()
+    }
+
+    fn decompress(packed: &[u8], table: &BlockTable) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        DecompressReader::new(Cursor::new(packed), table).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = text();
+        for (compression, method) in [(Compression::Zstd { level: 3 }, METHOD_ZSTD), (Compression::Lz4, METHOD_LZ4)] {
+            let (packed, table) = compress(&data, compression);
+            assert!(packed.len() < data.len() / 10, "{}", compression);
+            assert_eq!(table.plain_len, data.len() as u64);
+            assert_eq!(methods(&packed, &table), [method; 3]);
+            assert_eq!(table.frames.iter().map(|&len| len as usize).sum::<usize>(), packed.len());
+            assert_eq!(decompress(&packed, &table).unwrap(), data, "{}", compression);
+
+            // 定位到第二块中间，再回到开头
+            let mut reader = DecompressReader::new(Cursor::new(&packed), &table);
+            let offset = BLOCK_SIZE as u64 + 1000;
+            reader.seek(SeekFrom::Start(offset)).unwrap();
+            let mut chunk = vec![0u8; 500];
+            reader.read_exact(&mut chunk).unwrap();
+            assert_eq!(chunk, data[offset as usize..offset as usize + 500]);
+            reader.seek(SeekFrom::Start(0)).unwrap();
+            reader.read_exact(&mut chunk).unwrap();
+            assert_eq!(chunk, data[..500]);
+        }
+    }
+
+    #[test]
+    fn none_writes_plain_data() {
+        let data = text();
+        let mut writer = CompressWriter::new(Vec::new(), Compression::None);
+        writer.write_all(&data).unwrap();
+        assert_eq!(writer.position(), data.len() as u64);
+        let (packed, table) = writer.finish().unwrap();
+        assert_eq!(packed, data);
+        assert!(table.is_none());
+    }
+
+    #[test]
+    fn stores_incompressible_blocks() {
+        // 压缩后不变小的块原样存储
+        let data = noise(BLOCK_SIZE + 300);
+        for compression in [Compression::Zstd { level: 3 }, Compression::Lz4] {
+            let (packed, table) = compress(&data, compression);
+            assert_eq!(methods(&packed, &table), [METHOD_STORED; 2]);
+            assert_eq!(packed.len(), data.len() + 2 * FRAME_HEADER_LEN);
+            assert_eq!(decompress(&packed, &table).unwrap(), data);
+        }
+
+        // 大半来自已压缩文件的块不尝试压缩，其余块照常压缩
+        let data = text();
+        let mut writer = CompressWriter::new(Vec::new(), Compression::Zstd { level: 3 });
+        writer.write_all(&data[..BLOCK_SIZE / 4]).unwrap();
+        writer.mark_incompressible(BLOCK_SIZE as u64);
+        writer.write_all(&data[BLOCK_SIZE / 4..]).unwrap();
+        let (packed, table) = writer.finish().unwrap();
+        let table = table.unwrap();
+        assert_eq!(methods(&packed, &table), [METHOD_STORED, METHOD_ZSTD, METHOD_ZSTD]);
+        assert_eq!(decompress(&packed, &table).unwrap(), data);
+
+        assert!(is_precompressed(Path::new("photos/IMG_0001.JPG")));
+        assert!(is_precompressed(Path::new("backup.tar.zst")));
+        assert!(!is_precompressed(Path::new("notes.txt")));
+        assert!(!is_precompressed(Path::new("jpg")));
+    }
+
+    #[test]
+    fn rejects_corrupted_block_tables() {
+        let data = text();
+        let (packed, table) = compress(&data, Compression::Zstd { level: 3 });
+        let rejects = |table: &BlockTable| {
+            let error = decompress(&packed, table).unwrap_err();
+            assert!(matches!(error.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof), "{}", error);
+        };
+
+        // 帧长度错位，第二帧从错误的位置读起
+        let mut shifted = table.clone();
+        shifted.frames[0] += 1;
+        rejects(&shifted);
+        // 帧数少于解压后长度需要的块数
+        let mut missing = table.clone();
+        missing.frames.pop();
+        rejects(&missing);
+        // 解压后长度与帧内容不符
+        let mut longer = table.clone();
+        longer.plain_len += 1;
+        rejects(&longer);
+        let mut shorter = table.clone();
+        shorter.plain_len -= BLOCK_SIZE as u64 / 4;
+        rejects(&shorter);
+
+        // 帧头中的长度超过块大小、未知的压缩方式、帧数据被改动
+        let mut oversized = packed.clone();
+        oversized[1..5].copy_from_slice(&(BLOCK_SIZE as u32 + 1).to_le_bytes());
+        let mut unknown = packed.clone();
+        unknown[0] = 9;
+        let mut flipped = packed.clone();
+        flipped[FRAME_HEADER_LEN + 10] ^= 0xFF;
+        for corrupted in [oversized, unknown, flipped] {
+            let error = decompress(&corrupted, &table).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+        }
+    }
+
+    #[test]
+    fn validates_zstd_levels() {
+        assert!(Compression::Zstd { level: 22 }.validate().is_ok());
+        assert!(Compression::Zstd { level: 0 }.validate().is_err());
+        assert!(Compression::Zstd { level: 23 }.validate().is_err());
+        for compression in [Compression::Zstd { level: 19 }, Compression::Lz4] {
+            assert_eq!(Compression::from_field(&compression.to_field().unwrap()).unwrap(), compression);
+        }
+        assert!(Compression::from_field(&[7]).is_err());
+    }
+}
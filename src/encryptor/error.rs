@@ -0,0 +1,59 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// 加解密过程中的错误，调用方可以按类型分别处理，而不必解析错误文本
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EncryptorError {
+    // 密码错误（头部 MAC 校验失败）
+    WrongPassword,
+    // 给出的密码和私钥都不是归档的接收者
+    NoMatchingKey,
+    // 密文被修改、截断或损坏（认证标签校验失败）
+    Corrupted,
+    // 要加密的文件夹或要解密的文件不存在
+    SourceMissing(PathBuf),
+    // 输出位置已存在，且冲突策略为 Fail
+    OutputExists(PathBuf),
+    // 不是本工具生成的归档，或使用了不支持的版本、算法
+    UnsupportedFormat(String),
+    // 参数不合法，如加密参数超出范围、选择性解压的路径不存在
+    InvalidOptions(String),
+    // 任务被 CancelToken 取消，部分输出已删除
+    Cancelled,
+    // 读写文件失败，context 说明失败的步骤
+    Io { context: String, source: io::Error },
+}
+
+impl EncryptorError {
+    // 用于 map_err，为 I/O 错误附加步骤说明
+    pub fn io(context: &str) -> impl Fn(io::Error) -> EncryptorError + Copy + '_ {
+        move |source| EncryptorError::Io { context: context.to_string(), source }
+    }
+}
+
+impl fmt::Display for EncryptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptorError::WrongPassword => write!(f, "Decryption failed: Incorrect password"),
+            EncryptorError::NoMatchingKey => write!(f, "Decryption failed: None of the given passwords or keys can open this archive"),
+            EncryptorError::Corrupted => write!(f, "Decryption failed: The file is corrupted or has been modified"),
+            EncryptorError::SourceMissing(path) => write!(f, "'{}' does not exist", path.display()),
+            EncryptorError::OutputExists(path) => write!(f, "'{}' already exists", path.display()),
+            EncryptorError::UnsupportedFormat(reason) => write!(f, "Unsupported archive: {}", reason),
+            EncryptorError::InvalidOptions(reason) => write!(f, "Invalid options: {}", reason),
+            EncryptorError::Cancelled => write!(f, "Operation cancelled"),
+            EncryptorError::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for EncryptorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncryptorError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
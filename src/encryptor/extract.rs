@@ -0,0 +1,285 @@
+// 安全解压：每个条目在写入前检查路径和类型，确保所有内容都落在目标文件夹内。
+// 不安全的条目不会被写入，而是记录下来随结果返回。
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path};
+
+use super::metadata::Restore;
+use super::progress::{Phase, Tracker};
+use super::select::Selection;
+
+// 条目被拒绝的原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RejectReason {
+    // 绝对路径
+    AbsolutePath,
+    // 路径中的 ".." 指向目标文件夹之外
+    PathEscapes,
+    // 符号链接指向目标文件夹之外
+    EscapingSymlink,
+    // 硬链接指向目标文件夹之外
+    EscapingHardlink,
+    // 块设备或字符设备
+    DeviceNode,
+    // FIFO 等其他特殊文件
+    SpecialFile,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            RejectReason::AbsolutePath => "absolute path",
+            RejectReason::PathEscapes => "path leaves the destination folder",
+            RejectReason::EscapingSymlink => "symlink points outside the destination folder",
+            RejectReason::EscapingHardlink => "hard link points outside the destination folder",
+            RejectReason::DeviceNode => "device node",
+            RejectReason::SpecialFile => "special file",
+        };
+        f.write_str(reason)
+    }
+}
+
+// 被拒绝、没有解压的条目
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RejectedEntry {
+    pub path: String,
+    pub reason: RejectReason,
+}
+
+// 逐个检查并解压条目，上报进度，返回被拒绝的条目。有 selection 时只解压选中的条目，其余条目的内容直接跳过。
+// 与 tar::Archive::unpack 一样，目录放到最后处理，避免只读目录的权限先生效导致其中的文件写不进去。
+// 权限位（包括 setuid、setgid、sticky）、时间和扩展属性都会还原；ownership 为 true 时还尽量还原属主。
+pub fn unpack<R: Read + Seek>(
+    archive: &mut tar::Archive<R>,
+    destination: &Path,
+    selection: Option<&Selection>,
+    ownership: bool,
+    tracker: &Tracker,
+) -> io::Result<Vec<RejectedEntry>> {
+    let root = destination.canonicalize()?;
+    archive.set_preserve_permissions(true);
+    let mut directories = Vec::new();
+    let mut rejected = Vec::new();
+    for entry in archive.entries_with_seek()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if selection.is_some_and(|selection| !selection.matches(&path.to_string_lossy())) {
+            continue;
+        }
+        tracker.start_entry(&path.to_string_lossy())?;
+
+        if let Some(reason) = check(&entry, &path, &root)? {
+            rejected.push(RejectedEntry { path: path.to_string_lossy().into_owned(), reason });
+        } else if entry.header().entry_type().is_dir() {
+            let restore = Restore::from_entry(&mut entry, ownership)?;
+            directories.push((entry, path, restore));
+        } else {
+            let restore = Restore::from_entry(&mut entry, ownership)?;
+            if entry.unpack_in(&root)? {
+                restore.apply(&root.join(&path))?;
+            }
+        }
+        tracker.finish_entry();
+    }
+    tracker.set_phase(Phase::Finalize);
+    // 从最深处往上处理，创建空子目录不会再改动已还原的上级目录修改时间
+    for (mut directory, path, restore) in directories.into_iter().rev() {
+        if directory.unpack_in(&root)? {
+            restore.apply(&root.join(&path))?;
+        }
+    }
+    Ok(rejected)
+}
+
+fn check<R: Read>(entry: &tar::Entry<R>, path: &Path, root: &Path) -> io::Result<Option<RejectReason>> {
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Ok(Some(RejectReason::AbsolutePath)),
+            Component::ParentDir => return Ok(Some(RejectReason::PathEscapes)),
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+
+    let entry_type = entry.header().entry_type();
+    if entry_type.is_block_special() || entry_type.is_character_special() {
+        return Ok(Some(RejectReason::DeviceNode));
+    }
+    if entry_type.is_fifo() {
+        return Ok(Some(RejectReason::SpecialFile));
+    }
+
+    if entry_type.is_symlink() || entry_type.is_hard_link() {
+        let escaping = if entry_type.is_symlink() {
+            RejectReason::EscapingSymlink
+        } else {
+            RejectReason::EscapingHardlink
+        };
+        let Some(link) = entry.link_name()? else {
+            return Ok(None);
+        };
+        // 硬链接相对于归档根目录解析。符号链接相对于自身所在目录解析，且不能离开所在的顶层文件夹，
+        // 因为顶层文件夹解压后会被移动到最终位置，指向外面的链接会指到用户的其他文件。
+        // 绝对路径一律拒绝。
+        let (base, boundary) = if entry_type.is_symlink() {
+            let mut components = path.components();
+            let top = components.next().filter(|_| components.next().is_some());
+            let boundary = top.map(|top| root.join(top)).unwrap_or_else(|| root.to_path_buf());
+            (path.parent().unwrap_or(Path::new("")), boundary)
+        } else {
+            (Path::new(""), root.to_path_buf())
+        };
+        if !resolves_inside(root, &boundary, &base.join(&link)) {
+            return Ok(Some(escaping));
+        }
+    }
+    Ok(None)
+}
+
+// 从 root 出发逐段解析 relative，判断结果是否始终在 boundary 内（boundary 位于 root 之下）。
+// 途经已解压的符号链接时按其实际指向继续，这些链接在写入时已经检查过。
+fn resolves_inside(root: &Path, boundary: &Path, relative: &Path) -> bool {
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return false,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if current == root || !current.pop() {
+                    return false;
+                }
+                // 中间经过 boundary 之外的祖先目录也不允许
+                if !current.starts_with(boundary) {
+                    return false;
+                }
+            }
+            Component::Normal(part) => {
+                current.push(part);
+                if is_symlink(&current) {
+                    match current.canonicalize() {
+                        Ok(target) => current = target,
+                        // 悬空链接无法继续解析，按不安全处理
+                        Err(_) => return false,
+                    }
+                }
+            }
+        }
+        if !current.starts_with(root) {
+            return false;
+        }
+    }
+    current.starts_with(boundary)
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // tar::Builder 拒绝 ".." 和绝对路径，这里直接写 tar 头中的名称字段
+    fn append(builder: &mut tar::Builder<Vec<u8>>, path: &str, kind: tar::EntryType, link: Option<&str>, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        let old = header.as_old_mut();
+        old.name[..path.len()].copy_from_slice(path.as_bytes());
+        if let Some(link) = link {
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_entry_type(kind);
+        header.set_mode(if kind.is_dir() { 0o755 } else { 0o644 });
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    // 在临时目录下的 dest 中解压，返回临时目录和被拒绝的条目
+    fn unpack_entries(build: impl FnOnce(&mut tar::Builder<Vec<u8>>, &Path)) -> (tempfile::TempDir, Vec<(String, RejectReason)>) {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().join("dest");
+        fs::create_dir(&destination).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        build(&mut builder, temp.path());
+        let mut archive = tar::Archive::new(Cursor::new(builder.into_inner().unwrap()));
+        let tracker = Tracker::new(None, None, Phase::Extract);
+        let rejected = unpack(&mut archive, &destination, None, false, &tracker).unwrap();
+        (temp, rejected.into_iter().map(|entry| (entry.path, entry.reason)).collect())
+    }
+
+    #[test]
+    fn rejects_parent_paths() {
+        let (temp, rejected) = unpack_entries(|builder, _| {
+            append(builder, "../x", tar::EntryType::Regular, None, b"outside");
+            append(builder, "top/../../y", tar::EntryType::Regular, None, b"outside");
+            append(builder, "top/ok.txt", tar::EntryType::Regular, None, b"inside");
+        });
+        assert_eq!(rejected, [("../x".to_string(), RejectReason::PathEscapes), ("top/../../y".to_string(), RejectReason::PathEscapes)]);
+        assert!(!temp.path().join("x").exists());
+        assert!(!temp.path().join("y").exists());
+        assert_eq!(fs::read(temp.path().join("dest/top/ok.txt")).unwrap(), b"inside");
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let (temp, rejected) = unpack_entries(|builder, temp| {
+            let target = temp.join("absolute.txt");
+            append(builder, target.to_str().unwrap(), tar::EntryType::Regular, None, b"outside");
+        });
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].1, RejectReason::AbsolutePath);
+        assert!(!temp.path().join("absolute.txt").exists());
+    }
+
+    // 先放一个指向外面的符号链接，再通过它写文件
+    #[test]
+    fn rejects_escaping_symlinks() {
+        let (temp, rejected) = unpack_entries(|builder, temp| {
+            fs::create_dir(temp.join("outside")).unwrap();
+            append(builder, "top/link", tar::EntryType::Symlink, Some("../../outside"), b"");
+            append(builder, "top/link/file.txt", tar::EntryType::Regular, None, b"through link");
+            append(builder, "escape", tar::EntryType::Symlink, Some(temp.to_str().unwrap()), b"");
+            // 不能离开所在的顶层文件夹，即使仍在解压目录内
+            append(builder, "other/link", tar::EntryType::Symlink, Some("../top"), b"");
+            append(builder, "top/inside", tar::EntryType::Symlink, Some("link/.."), b"");
+        });
+        let reasons: Vec<_> = rejected.iter().map(|(path, reason)| (path.as_str(), *reason)).collect();
+        assert_eq!(
+            reasons,
+            [
+                ("top/link", RejectReason::EscapingSymlink),
+                ("escape", RejectReason::EscapingSymlink),
+                ("other/link", RejectReason::EscapingSymlink),
+            ]
+        );
+        assert_eq!(fs::read_dir(temp.path().join("outside")).unwrap().count(), 0);
+        let dest = temp.path().join("dest");
+        // 链接没有创建，写入的是普通目录中的文件
+        assert!(!is_symlink(&dest.join("top/link")));
+        assert_eq!(fs::read(dest.join("top/link/file.txt")).unwrap(), b"through link");
+        assert!(is_symlink(&dest.join("top/inside")));
+    }
+
+    #[test]
+    fn rejects_escaping_hardlinks() {
+        let (temp, rejected) = unpack_entries(|builder, temp| {
+            fs::write(temp.join("secret.txt"), b"secret").unwrap();
+            append(builder, "top/a.txt", tar::EntryType::Regular, None, b"inside");
+            append(builder, "top/hard", tar::EntryType::Link, Some("../secret.txt"), b"");
+            append(builder, "top/absolute", tar::EntryType::Link, Some(temp.join("secret.txt").to_str().unwrap()), b"");
+            append(builder, "top/ok", tar::EntryType::Link, Some("top/a.txt"), b"");
+        });
+        let reasons: Vec<_> = rejected.iter().map(|(path, reason)| (path.as_str(), *reason)).collect();
+        assert_eq!(reasons, [("top/hard", RejectReason::EscapingHardlink), ("top/absolute", RejectReason::EscapingHardlink)]);
+        let dest = temp.path().join("dest");
+        assert!(!dest.join("top/hard").exists());
+        assert!(!dest.join("top/absolute").exists());
+        assert_eq!(fs::read(dest.join("top/ok")).unwrap(), b"inside");
+        assert_eq!(fs::read(temp.path().join("secret.txt")).unwrap(), b"secret");
+    }
+}
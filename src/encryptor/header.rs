@@ -0,0 +1,255 @@
+//! `.aes` 容器头格式
+//!
+//! ```text
+//! magic   6 字节   "PWENC\0"
+//! version 1 字节   格式版本
+//! fields  若干个   tag(1 字节) + len(2 字节, LE) + value，以 tag 0 结束
+//! mac     32 字节  HMAC-SHA256(header_key, magic..fields)
+//! ```
+//!
+//! 字段：
+//!
+//! | tag | 内容                                       |
+//! |-----|--------------------------------------------|
+//! | 1   | 加密算法 id（1 = XChaCha20-Poly1305）      |
+//! | 4   | 19 字节分块 nonce 前缀                      |
+//! | 5   | 空值，表示载荷之后带有条目索引（见 `index` 模块） |
+//! | 6   | 压缩算法 id（1 = zstd，后跟级别 i32 LE；2 = lz4），没有此字段表示不压缩 |
+//! | 7   | 接收者，可出现多次（见 `recipient` 模块）   |
+//!
+//! 密码接收者记录的 KDF 为 id + 参数，参数均为 u32 LE：
+//!
+//! - 1 = PBKDF2-SHA256：迭代次数
+//! - 2 = Argon2id：内存（KiB）、迭代次数、并行度
+//! - 3 = scrypt：log2(N)，r = 8、p = 1
+//! - 4 = 7-Zip 的迭代 SHA-256：log2(轮数)
+//! - 5 = PBKDF2-SHA1：迭代次数（zip 的 WinZip AES 使用）
+//!
+//! 密钥来自随机文件密钥，每个接收者的 tag 7 中各有一份包装后的文件密钥。
+//!
+//! 头部之后是载荷密文，认证时以不含接收者字段的头部作为附加数据，添加接收者不影响载荷。
+//! tar 载荷按 64 KiB 分块加密，见 `stream` 模块；有压缩字段时先按块压缩，见 `compress` 模块。
+
+use std::fmt;
+use std::io::Read;
+
+use super::compress::Compression;
+use super::error::EncryptorError;
+use super::kdf::Kdf;
+use super::recipient::Stanza;
+
+pub const MAGIC: &[u8; 6] = b"PWENC\0";
+pub const FORMAT_VERSION: u8 = 3;
+pub const MAC_LEN: usize = 32;
+
+const TAG_END: u8 = 0;
+const TAG_CIPHER: u8 = 1;
+const TAG_NONCE: u8 = 4;
+const TAG_INDEX: u8 = 5;
+const TAG_COMPRESSION: u8 = 6;
+const TAG_RECIPIENT: u8 = 7;
+
+const CIPHER_XCHACHA20_POLY1305: u8 = 1;
+const KDF_PBKDF2_SHA256: u8 = 1;
+const KDF_ARGON2ID: u8 = 2;
+const KDF_SCRYPT: u8 = 3;
+const KDF_SEVEN_ZIP: u8 = 4;
+const KDF_PBKDF2_SHA1: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Cipher {
+    XChaCha20Poly1305,
+    // age 文件使用
+    ChaCha20Poly1305,
+    // 旧版本的 OpenSSL 文件和 7z 归档使用，没有认证
+    Aes256Cbc,
+    // 没有加密的 7z、zip 归档
+    None,
+    // zip 归档的 WinZip AES：AES-CTR 加密，HMAC-SHA1 认证
+    WinZipAes,
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cipher::XChaCha20Poly1305 => write!(f, "XChaCha20-Poly1305"),
+            Cipher::ChaCha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
+            Cipher::Aes256Cbc => write!(f, "AES-256-CBC"),
+            Cipher::None => write!(f, "none"),
+            Cipher::WinZipAes => write!(f, "AES-CTR + HMAC-SHA1 (WinZip)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: Cipher,
+    pub nonce: Vec<u8>,
+    pub indexed: bool,
+    pub compression: Compression,
+    pub recipients: Vec<Stanza>,
+}
+
+impl Header {
+    // 序列化头部（不含 MAC）
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize(true)
+    }
+
+    // 载荷和索引密文的附加数据：不含接收者字段的头部
+    pub fn aad(&self) -> Vec<u8> {
+        self.serialize(false)
+    }
+
+    fn serialize(&self, with_recipients: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(self.version);
+
+        let cipher = match self.cipher {
+            Cipher::XChaCha20Poly1305 => CIPHER_XCHACHA20_POLY1305,
+            Cipher::ChaCha20Poly1305 => unreachable!("ChaCha20-Poly1305 is only used by age files"),
+            Cipher::Aes256Cbc => unreachable!("AES-256-CBC is only used by OpenSSL files and 7z archives"),
+            Cipher::None => unreachable!("native archives are always encrypted"),
+            Cipher::WinZipAes => unreachable!("WinZip AES is only used by zip archives"),
+        };
+        push_field(&mut out, TAG_CIPHER, &[cipher]);
+        push_field(&mut out, TAG_NONCE, &self.nonce);
+        if self.indexed {
+            push_field(&mut out, TAG_INDEX, &[]);
+        }
+        if let Some(compression) = self.compression.to_field() {
+            push_field(&mut out, TAG_COMPRESSION, &compression);
+        }
+        if with_recipients {
+            for recipient in &self.recipients {
+                push_field(&mut out, TAG_RECIPIENT, &recipient.to_field());
+            }
+        }
+        out.push(TAG_END);
+        out
+    }
+
+    // 读取并解析头部，返回头部、原始字节（用于校验 MAC）和 MAC
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<(Header, Vec<u8>, [u8; MAC_LEN]), EncryptorError> {
+        let not_archive = || EncryptorError::UnsupportedFormat("not an encrypted archive".to_string());
+        let mut raw = vec![0u8; MAGIC.len() + 1];
+        reader.read_exact(&mut raw).map_err(|_| not_archive())?;
+        if &raw[..MAGIC.len()] != MAGIC {
+            return Err(not_archive());
+        }
+
+        let version = raw[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(EncryptorError::UnsupportedFormat(format!("format version {}", version)));
+        }
+
+        let mut cipher = None;
+        let mut nonce = None;
+        let mut indexed = false;
+        let mut compression = Compression::None;
+        let mut recipients = Vec::new();
+
+        loop {
+            let mut tag = [0u8; 1];
+            read_raw(reader, &mut raw, &mut tag)?;
+            if tag[0] == TAG_END {
+                break;
+            }
+
+            let mut len = [0u8; 2];
+            read_raw(reader, &mut raw, &mut len)?;
+            let mut value = vec![0u8; u16::from_le_bytes(len) as usize];
+            read_raw(reader, &mut raw, &mut value)?;
+
+            match tag[0] {
+                TAG_CIPHER => cipher = Some(parse_cipher(&value)?),
+                TAG_NONCE => nonce = Some(value),
+                TAG_INDEX => indexed = true,
+                TAG_COMPRESSION => compression = Compression::from_field(&value)?,
+                TAG_RECIPIENT => recipients.push(Stanza::from_field(&value)?),
+                other => return Err(EncryptorError::UnsupportedFormat(format!("unknown header field {}", other))),
+            }
+        }
+
+        let mut mac = [0u8; MAC_LEN];
+        reader.read_exact(&mut mac).map_err(|_| EncryptorError::Corrupted)?;
+
+        let missing = |field: &str| EncryptorError::UnsupportedFormat(format!("header is missing the {}", field));
+        if recipients.is_empty() {
+            return Err(missing("recipients"));
+        }
+        let header = Header {
+            version,
+            cipher: cipher.ok_or_else(|| missing("cipher"))?,
+            nonce: nonce.ok_or_else(|| missing("nonce"))?,
+            indexed,
+            compression,
+            recipients,
+        };
+        Ok((header, raw, mac))
+    }
+}
+
+pub(super) fn push_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn read_raw<R: Read>(reader: &mut R, raw: &mut Vec<u8>, buf: &mut [u8]) -> Result<(), EncryptorError> {
+    reader.read_exact(buf).map_err(|_| EncryptorError::Corrupted)?;
+    raw.extend_from_slice(buf);
+    Ok(())
+}
+
+fn parse_cipher(value: &[u8]) -> Result<Cipher, EncryptorError> {
+    match value {
+        [CIPHER_XCHACHA20_POLY1305] => Ok(Cipher::XChaCha20Poly1305),
+        _ => Err(EncryptorError::UnsupportedFormat("unknown cipher".to_string())),
+    }
+}
+
+// KDF 字段的值：id + 各参数（u32 LE）
+pub(super) fn encode_kdf(kdf: &Kdf) -> Vec<u8> {
+    let (kdf_id, params) = match *kdf {
+        Kdf::Pbkdf2Sha256 { iterations } => (KDF_PBKDF2_SHA256, vec![iterations]),
+        Kdf::Argon2id { memory_kib, iterations, parallelism } => (KDF_ARGON2ID, vec![memory_kib, iterations, parallelism]),
+        Kdf::Scrypt { log_n } => (KDF_SCRYPT, vec![log_n as u32]),
+        Kdf::SevenZip { log_cycles } => (KDF_SEVEN_ZIP, vec![log_cycles as u32]),
+        Kdf::Pbkdf2Sha1 { iterations } => (KDF_PBKDF2_SHA1, vec![iterations]),
+    };
+    let mut value = vec![kdf_id];
+    for param in params {
+        value.extend_from_slice(&param.to_le_bytes());
+    }
+    value
+}
+
+pub(super) fn parse_kdf(value: &[u8]) -> Result<Kdf, EncryptorError> {
+    let unsupported = || EncryptorError::UnsupportedFormat("unsupported key derivation".to_string());
+    let (&kdf_id, params) = value.split_first().ok_or_else(unsupported)?;
+    let params: Vec<u32> = params
+        .chunks(4)
+        .map(|p| p.try_into().map(u32::from_le_bytes))
+        .collect::<Result<_, _>>()
+        .map_err(|_| unsupported())?;
+
+    let kdf = match (kdf_id, params.as_slice()) {
+        (KDF_PBKDF2_SHA256, &[iterations]) => Kdf::Pbkdf2Sha256 { iterations },
+        (KDF_ARGON2ID, &[memory_kib, iterations, parallelism]) => {
+            Kdf::Argon2id { memory_kib, iterations, parallelism }
+        }
+        (KDF_SCRYPT, &[log_n]) => Kdf::Scrypt { log_n: u8::try_from(log_n).map_err(|_| unsupported())? },
+        (KDF_SEVEN_ZIP, &[log_cycles]) => {
+            Kdf::SevenZip { log_cycles: u8::try_from(log_cycles).map_err(|_| unsupported())? }
+        }
+        (KDF_PBKDF2_SHA1, &[iterations]) => Kdf::Pbkdf2Sha1 { iterations },
+        _ => return Err(unsupported()),
+    };
+    // 参数超出允许范围时拒绝解密，避免恶意头部耗尽资源
+    kdf.validate().map_err(|_| unsupported())?;
+    Ok(kdf)
+}
@@ -0,0 +1,197 @@
+// 条目索引：记录每个条目在 tar 载荷中的偏移，列目录或提取单个文件时
+// 只需解密索引和相关的块。
+//
+// 索引作为独立的分块密文（使用单独的密钥）写在载荷之后，文件末尾是 footer：
+//
+// ```text
+// 索引密文起始偏移  8 字节 u64 LE（相对于载荷起点，重写头部时不变）
+// magic            8 字节 "PWINDEX\0"
+// ```
+//
+// 索引明文：条目数 u32 LE，每个条目为 u32 LE 长度 + 若干 tag/len/value 字段，
+// 读取时忽略不认识的字段，便于以后追加信息。
+//
+// 载荷经过压缩时，条目之后是块表：帧数 u32 LE、每帧长度 u32 LE、解压后总长度 u64 LE（见 `compress` 模块）。
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::compress::BlockTable;
+use super::error::EncryptorError;
+use super::header::push_field;
+
+pub const FOOTER_MAGIC: &[u8; 8] = b"PWINDEX\0";
+pub const FOOTER_LEN: u64 = 16;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_KIND: u8 = 2;
+const FIELD_SIZE: u8 = 3;
+const FIELD_OFFSET: u8 = 4;
+const FIELD_MTIME: u8 = 5;
+const FIELD_MODE: u8 = 6;
+const FIELD_SHA256: u8 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+impl EntryKind {
+    pub fn from_tar(entry_type: tar::EntryType) -> Self {
+        if entry_type.is_file() {
+            EntryKind::File
+        } else if entry_type.is_dir() {
+            EntryKind::Directory
+        } else if entry_type.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::Other
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    // 条目 tar 头在载荷明文中的偏移
+    pub offset: u64,
+    // 修改时间（Unix 秒）和权限位，与 tar 头中记录的一致；7z、zip 归档可能没有修改时间
+    pub mtime: Option<u64>,
+    pub mode: Option<u32>,
+    // 文件内容的 SHA-256，用于校验；非文件条目和 7z、zip 归档没有
+    pub sha256: Option<[u8; 32]>,
+}
+
+pub fn encode(entries: &[IndexEntry], blocks: Option<&BlockTable>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        let kind = match entry.kind {
+            EntryKind::File => 0u8,
+            EntryKind::Directory => 1,
+            EntryKind::Symlink => 2,
+            EntryKind::Other => 3,
+        };
+
+        let mut record = Vec::new();
+        push_field(&mut record, FIELD_PATH, entry.path.as_bytes());
+        push_field(&mut record, FIELD_KIND, &[kind]);
+        push_field(&mut record, FIELD_SIZE, &entry.size.to_le_bytes());
+        push_field(&mut record, FIELD_OFFSET, &entry.offset.to_le_bytes());
+        if let Some(mtime) = entry.mtime {
+            push_field(&mut record, FIELD_MTIME, &mtime.to_le_bytes());
+        }
+        if let Some(mode) = entry.mode {
+            push_field(&mut record, FIELD_MODE, &mode.to_le_bytes());
+        }
+        if let Some(sha256) = &entry.sha256 {
+            push_field(&mut record, FIELD_SHA256, sha256);
+        }
+
+        out.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        out.extend_from_slice(&record);
+    }
+
+    if let Some(blocks) = blocks {
+        out.extend_from_slice(&(blocks.frames.len() as u32).to_le_bytes());
+        for len in &blocks.frames {
+            out.extend_from_slice(&len.to_le_bytes());
+        }
+        out.extend_from_slice(&blocks.plain_len.to_le_bytes());
+    }
+    out
+}
+
+pub fn decode(data: &[u8]) -> Result<(Vec<IndexEntry>, Option<BlockTable>), EncryptorError> {
+    let malformed = || EncryptorError::UnsupportedFormat("archive index is malformed".to_string());
+    let mut rest = data;
+
+    let count = u32::from_le_bytes(take(&mut rest, 4).ok_or_else(malformed)?.try_into().unwrap());
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let len = u32::from_le_bytes(take(&mut rest, 4).ok_or_else(malformed)?.try_into().unwrap());
+        let mut record = take(&mut rest, len as usize).ok_or_else(malformed)?;
+
+        let mut entry = IndexEntry {
+            path: String::new(),
+            kind: EntryKind::Other,
+            size: 0,
+            offset: 0,
+            mtime: None,
+            mode: None,
+            sha256: None,
+        };
+        while !record.is_empty() {
+            let tag = take(&mut record, 1).ok_or_else(malformed)?[0];
+            let len = u16::from_le_bytes(take(&mut record, 2).ok_or_else(malformed)?.try_into().unwrap());
+            let value = take(&mut record, len as usize).ok_or_else(malformed)?;
+
+            match tag {
+                FIELD_PATH => entry.path = String::from_utf8_lossy(value).into_owned(),
+                FIELD_KIND => {
+                    entry.kind = match value {
+                        [0] => EntryKind::File,
+                        [1] => EntryKind::Directory,
+                        [2] => EntryKind::Symlink,
+                        _ => EntryKind::Other,
+                    }
+                }
+                FIELD_SIZE => entry.size = u64::from_le_bytes(value.try_into().map_err(|_| malformed())?),
+                FIELD_OFFSET => entry.offset = u64::from_le_bytes(value.try_into().map_err(|_| malformed())?),
+                FIELD_MTIME => entry.mtime = Some(u64::from_le_bytes(value.try_into().map_err(|_| malformed())?)),
+                FIELD_MODE => entry.mode = Some(u32::from_le_bytes(value.try_into().map_err(|_| malformed())?)),
+                FIELD_SHA256 => entry.sha256 = Some(value.try_into().map_err(|_| malformed())?),
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+
+    if rest.is_empty() {
+        return Ok((entries, None));
+    }
+    let count = u32::from_le_bytes(take(&mut rest, 4).ok_or_else(malformed)?.try_into().unwrap());
+    let frames = take(&mut rest, count as usize * 4)
+        .ok_or_else(malformed)?
+        .chunks(4)
+        .map(|len| u32::from_le_bytes(len.try_into().unwrap()))
+        .collect();
+    let plain_len = u64::from_le_bytes(take(&mut rest, 8).ok_or_else(malformed)?.try_into().unwrap());
+    Ok((entries, Some(BlockTable { frames, plain_len })))
+}
+
+// 读取 footer，返回索引密文在文件中的起始偏移
+pub fn read_footer<R: Read + Seek>(reader: &mut R, payload_start: u64) -> io::Result<u64> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "archive index footer is missing");
+    if len < payload_start + FOOTER_LEN {
+        return Err(invalid());
+    }
+
+    let mut footer = [0u8; FOOTER_LEN as usize];
+    reader.seek(SeekFrom::Start(len - FOOTER_LEN))?;
+    reader.read_exact(&mut footer)?;
+    if &footer[8..] != FOOTER_MAGIC {
+        return Err(invalid());
+    }
+
+    let offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    let offset = offset.checked_add(payload_start).ok_or_else(invalid)?;
+    if offset > len - FOOTER_LEN {
+        return Err(invalid());
+    }
+    Ok(offset)
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Some(head)
+}
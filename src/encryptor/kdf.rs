@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::error::EncryptorError;
+
+// 解密时允许的最大开销，防止恶意头部耗尽内存或长时间占用 CPU
+const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MIN_MEMORY_KIB: u32 = 8 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+// PBKDF2 的迭代次数上限，常见取值（OpenSSL 1 万、zip 1000、OWASP 建议的 60 万）都远低于此
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+// scrypt 的 N 最大为 2^22（4 GiB 内存），与 age 解密时的默认上限相同
+const MAX_SCRYPT_LOG_N: u8 = 22;
+// 7-Zip 允许的最大迭代次数为 2^24
+const MAX_SEVEN_ZIP_LOG_CYCLES: u8 = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2Sha256 { iterations: u32 },
+    Argon2id { memory_kib: u32, iterations: u32, parallelism: u32 },
+    // r = 8、p = 1，N = 2^log_n；age 文件的密码接收者使用
+    Scrypt { log_n: u8 },
+    // SHA-256 迭代 2^log_cycles 次，密码按 UTF-16LE 编码；7z 归档使用
+    SevenZip { log_cycles: u8 },
+    // zip 归档的 WinZip AES 使用，固定迭代 1000 次
+    Pbkdf2Sha1 { iterations: u32 },
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id { memory_kib: 64 * 1024, iterations: 3, parallelism: 4 }
+    }
+}
+
+impl Kdf {
+    pub fn validate(&self) -> Result<(), EncryptorError> {
+        match *self {
+            Kdf::Pbkdf2Sha256 { iterations } | Kdf::Pbkdf2Sha1 { iterations }
+                if (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations) =>
+            {
+                Ok(())
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism }
+                if memory_kib <= MAX_MEMORY_KIB
+                    && iterations <= MAX_ITERATIONS
+                    && Params::new(memory_kib, iterations, parallelism, Some(32)).is_ok() =>
+            {
+                Ok(())
+            }
+            Kdf::Scrypt { log_n } if (1..=MAX_SCRYPT_LOG_N).contains(&log_n) => Ok(()),
+            Kdf::SevenZip { log_cycles } if log_cycles <= MAX_SEVEN_ZIP_LOG_CYCLES => Ok(()),
+            _ => Err(EncryptorError::InvalidOptions("key derivation parameters are out of range".to_string())),
+        }
+    }
+
+    // 从密码派生 32 字节主密钥
+    pub fn derive(&self, password: &str, salt: &[u8]) -> Result<[u8; 32], EncryptorError> {
+        self.validate()?;
+
+        let mut master = [0u8; 32];
+        match *self {
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut master);
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(master.len()))
+                    .map_err(|e| EncryptorError::InvalidOptions(e.to_string()))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password.as_bytes(), salt, &mut master)
+                    .map_err(|e| EncryptorError::InvalidOptions(e.to_string()))?;
+            }
+            Kdf::Pbkdf2Sha1 { iterations } => {
+                pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, iterations, &mut master);
+            }
+            Kdf::Scrypt { log_n } => {
+                let params = scrypt::Params::new(log_n, 8, 1, master.len())
+                    .map_err(|e| EncryptorError::InvalidOptions(e.to_string()))?;
+                scrypt::scrypt(password.as_bytes(), salt, &params, &mut master).expect("valid output length");
+            }
+            Kdf::SevenZip { log_cycles } => {
+                // 每轮输入 盐 + 密码 + 轮次（u64 LE），整个过程只计算一次哈希
+                let password: Vec<u8> = password.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                let mut hasher = Sha256::new();
+                for round in 0..1u64 << log_cycles {
+                    hasher.update(salt);
+                    hasher.update(&password);
+                    hasher.update(round.to_le_bytes());
+                }
+                master = hasher.finalize().into();
+            }
+        }
+        Ok(master)
+    }
+}
+
+// 在当前机器上测量 Argon2id 耗时，选出解锁时间接近 target 的参数。
+// 先用默认内存调整迭代次数；若单次迭代已超时，再逐步减半内存。
+pub fn calibrate(target: Duration) -> Kdf {
+    let Kdf::Argon2id { mut memory_kib, parallelism, .. } = Kdf::default() else {
+        unreachable!("default KDF is Argon2id");
+    };
+    let salt = [0u8; 16];
+
+    loop {
+        let probe = Kdf::Argon2id { memory_kib, iterations: 1, parallelism };
+        let start = Instant::now();
+        if probe.derive("calibration", &salt).is_err() {
+            return Kdf::default();
+        }
+        let elapsed = start.elapsed().max(Duration::from_millis(1));
+
+        if elapsed > target && memory_kib > MIN_MEMORY_KIB {
+            memory_kib /= 2;
+            continue;
+        }
+
+        let iterations = (target.as_secs_f64() / elapsed.as_secs_f64()).floor() as u32;
+        return Kdf::Argon2id {
+            memory_kib,
+            iterations: iterations.clamp(1, MAX_ITERATIONS),
+            parallelism,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryptor::testutil::hex;
+
+    // RFC 7914 第 12 节；前两组的 r、p 与 age 不同，直接调用 scrypt
+    #[test]
+    fn scrypt_rfc7914_vectors() {
+        let cases: [(&str, &str, u8, u32, u32, &str); 2] = [
+            (
+                "",
+                "",
+                4,
+                1,
+                1,
+                "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906",
+            ),
+            (
+                "password",
+                "NaCl",
+                10,
+                8,
+                16,
+                "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640",
+            ),
+        ];
+        for (password, salt, log_n, r, p, expected) in cases {
+            let mut output = [0u8; 64];
+            let params = scrypt::Params::new(log_n, r, p, output.len()).unwrap();
+            scrypt::scrypt(password.as_bytes(), salt.as_bytes(), &params, &mut output).unwrap();
+            assert_eq!(output.to_vec(), hex(expected));
+        }
+
+        // N = 16384、r = 8、p = 1，取前 32 字节
+        let master = Kdf::Scrypt { log_n: 14 }.derive("pleaseletmein", b"SodiumChloride").unwrap();
+        assert_eq!(master.to_vec(), hex("7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2"));
+    }
+
+    #[test]
+    fn scrypt_work_factor_is_limited() {
+        assert!(Kdf::Scrypt { log_n: MAX_SCRYPT_LOG_N }.validate().is_ok());
+        assert!(Kdf::Scrypt { log_n: MAX_SCRYPT_LOG_N + 1 }.validate().is_err());
+        assert!(Kdf::Scrypt { log_n: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn pbkdf2_iterations_are_limited() {
+        let kinds: [fn(u32) -> Kdf; 2] =
+            [|iterations| Kdf::Pbkdf2Sha256 { iterations }, |iterations| Kdf::Pbkdf2Sha1 { iterations }];
+        for kdf in kinds {
+            assert!(kdf(MAX_PBKDF2_ITERATIONS).validate().is_ok());
+            assert!(kdf(MAX_PBKDF2_ITERATIONS + 1).validate().is_err());
+            assert!(kdf(0).validate().is_err());
+        }
+    }
+}
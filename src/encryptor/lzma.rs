@@ -0,0 +1,173 @@
+// 7z 归档中 LZMA、LZMA2 解码器的创建，解码本身由 lzma-rust2 完成。
+//
+// 解码器按窗口大小一次分配内存。字典大小和解压后长度都来自不可信的 7z 头部，窗口取两者中的
+// 较小值，超过 MAX_WINDOW 时报不支持，避免很小的恶意归档让程序先分配数 GiB 内存。
+
+use std::io::{self, Read};
+
+use lzma_rust2::{DICT_SIZE_MIN, Lzma2Reader, LzmaReader};
+
+// 7-Zip 各压缩级别使用的字典最大为 64 MiB（-mx9）
+const MAX_WINDOW: u64 = 64 * 1024 * 1024;
+// LZMA2 属性字节的最大值，对应 4 GiB - 1 的字典
+const LZMA2_MAX_PROP: u8 = 40;
+
+fn corrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "LZMA properties are corrupted")
+}
+
+fn window_size(dict_size: u64, unpack_size: u64) -> io::Result<u32> {
+    let size = dict_size.min(unpack_size).max(DICT_SIZE_MIN as u64);
+    if size > MAX_WINDOW {
+        let message = format!("LZMA dictionaries larger than {} MiB are not supported", MAX_WINDOW >> 20);
+        return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+    }
+    Ok(size as u32)
+}
+
+// lzma-rust2 把部分数据错误（如匹配距离超出已解码的数据）报为 Other，这里统一改为 InvalidData
+pub(super) struct Checked<D: Read>(D);
+
+impl<D: Read> Read for Checked<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::Other => io::Error::new(io::ErrorKind::InvalidData, e),
+            _ => e,
+        })
+    }
+}
+
+// LZMA 数据流，属性为 5 字节（lc/lp/pb + 字典大小 u32 LE），解压后长度由 7z 头部给出
+pub(super) fn lzma_reader<R: Read>(inner: R, properties: &[u8], unpack_size: u64) -> io::Result<Checked<LzmaReader<R>>> {
+    let [lclppb, dict @ ..] = properties else {
+        return Err(corrupted());
+    };
+    let dict_size: [u8; 4] = dict.try_into().map_err(|_| corrupted())?;
+    let window = window_size(u32::from_le_bytes(dict_size) as u64, unpack_size)?;
+    LzmaReader::new_with_props(inner, unpack_size, *lclppb, window, None).map(Checked)
+}
+
+// LZMA2 数据流，属性为 1 字节的字典大小
+pub(super) fn lzma2_reader<R: Read>(inner: R, properties: &[u8], unpack_size: u64) -> io::Result<Checked<Lzma2Reader<R>>> {
+    let &[property] = properties else {
+        return Err(corrupted());
+    };
+    if property > LZMA2_MAX_PROP {
+        return Err(corrupted());
+    }
+    let dict_size = if property == LZMA2_MAX_PROP {
+        u32::MAX as u64
+    } else {
+        (2 | (property & 1) as u64) << (property / 2 + 11)
+    };
+    Ok(Checked(Lzma2Reader::new(inner, window_size(dict_size, unpack_size)?, None)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use lzma_rust2::{Lzma2Options, Lzma2Writer, LzmaOptions, LzmaWriter};
+
+    use super::*;
+    use crate::encryptor::vtar::is_data_error;
+
+    // 1 MiB 字典对应的 LZMA2 属性字节
+    const LZMA2_PROP_1_MIB: u8 = 16;
+
+    fn sample() -> Vec<u8> {
+        (0..200_000u32).map(|i| if i % 3 == 0 { (i >> 8) as u8 } else { b"lzma"[i as usize % 4] }).collect()
+    }
+
+    // 返回属性和压缩数据
+    fn compress_lzma(data: &[u8], end_marker: bool) -> (Vec<u8>, Vec<u8>) {
+        let options = LzmaOptions::with_preset(1);
+        let mut writer = LzmaWriter::new_no_header(Vec::new(), &options, end_marker).unwrap();
+        let mut properties = vec![writer.props()];
+        properties.extend_from_slice(&options.dict_size.to_le_bytes());
+        writer.write_all(data).unwrap();
+        (properties, writer.finish().unwrap())
+    }
+
+    fn compress_lzma2(data: &[u8]) -> Vec<u8> {
+        let mut writer = Lzma2Writer::new(Vec::new(), Lzma2Options::with_preset(1));
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decode(reader: io::Result<impl Read>) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        reader?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn decode_lzma(packed: &[u8], properties: &[u8], unpack_size: u64) -> io::Result<Vec<u8>> {
+        decode(lzma_reader(packed, properties, unpack_size))
+    }
+
+    fn decode_lzma2(packed: &[u8], unpack_size: u64) -> io::Result<Vec<u8>> {
+        decode(lzma2_reader(packed, &[LZMA2_PROP_1_MIB], unpack_size))
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = sample();
+        for end_marker in [false, true] {
+            let (properties, packed) = compress_lzma(&data, end_marker);
+            assert_eq!(decode_lzma(&packed, &properties, data.len() as u64).unwrap(), data);
+        }
+        assert_eq!(decode_lzma2(&compress_lzma2(&data), data.len() as u64).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        let data = sample();
+        let (properties, packed) = compress_lzma(&data, false);
+        let error = decode_lzma(&packed[..packed.len() / 2], &properties, data.len() as u64).unwrap_err();
+        assert!(is_data_error(&error), "{}", error);
+
+        let packed = compress_lzma2(&data);
+        let error = decode_lzma2(&packed[..packed.len() / 2], data.len() as u64).unwrap_err();
+        assert!(is_data_error(&error), "{}", error);
+    }
+
+    // 改动压缩数据后要么报错，要么解出不同的内容（由 7z 的 CRC32 发现），不会 panic
+    #[test]
+    fn corrupted_streams_do_not_decode_silently() {
+        let data = sample();
+        let (properties, packed) = compress_lzma(&data, false);
+        let packed2 = compress_lzma2(&data);
+        for position in (0..packed.len().min(packed2.len())).step_by(97) {
+            let mut corrupted = packed.clone();
+            corrupted[position] ^= 0x55;
+            assert_ne!(decode_lzma(&corrupted, &properties, data.len() as u64).ok(), Some(data.clone()));
+            let mut corrupted = packed2.clone();
+            corrupted[position] ^= 0x55;
+            assert_ne!(decode_lzma2(&corrupted, data.len() as u64).ok(), Some(data.clone()));
+        }
+
+        // 无效的属性字节和 LZMA2 控制字节
+        assert!(is_data_error(&decode_lzma(&packed, &[225, 0, 0, 16, 0], data.len() as u64).unwrap_err()));
+        assert!(is_data_error(&decode_lzma(&packed, &[93, 0, 0], data.len() as u64).unwrap_err()));
+        let mut invalid = packed2.clone();
+        invalid[0] = 0x03;
+        assert!(is_data_error(&decode_lzma2(&invalid, data.len() as u64).unwrap_err()));
+        assert!(is_data_error(&decode(lzma2_reader(packed2.as_slice(), &[LZMA2_MAX_PROP + 1], 10)).unwrap_err()));
+    }
+
+    // 头部给出的字典和解压后长度都很大时不分配窗口；只有字典很大时按解压后长度分配
+    #[test]
+    fn limits_window_size() {
+        let data = sample();
+        let (_, packed) = compress_lzma(&data, false);
+        let huge_dict = [93, 0xFF, 0xFF, 0xFF, 0xFF];
+        let error = lzma_reader(packed.as_slice(), &huge_dict, u64::MAX).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        let error = lzma2_reader(packed.as_slice(), &[LZMA2_MAX_PROP], 1 << 40).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+
+        assert_eq!(decode_lzma(&packed, &huge_dict, data.len() as u64).unwrap(), data);
+        let packed2 = compress_lzma2(&data);
+        assert_eq!(decode(lzma2_reader(packed2.as_slice(), &[LZMA2_MAX_PROP], data.len() as u64)).unwrap(), data);
+    }
+}
@@ -0,0 +1,268 @@
+// POSIX 元数据的保存和还原。
+//
+// tar 头中已有权限位、uid/gid 和秒级修改时间。纳秒级的修改时间、访问时间和扩展属性
+// 以 PAX 扩展头（"mtime"、"atime"、"SCHILY.xattr.<name>"，与 GNU tar 相同）写在条目之前。
+// 解压时 tar 先还原内容、权限和文件的秒级修改时间，再由这里补上属主、扩展属性、精确时间和目录的修改时间。
+// 属主和扩展属性尽力而为：普通用户无权改属主、文件系统不支持扩展属性时跳过，不影响解压。
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use filetime::FileTime;
+
+const PAX_MTIME: &str = "mtime";
+const PAX_ATIME: &str = "atime";
+const PAX_XATTR: &str = "SCHILY.xattr.";
+const PAX_HEADER_NAME: &str = "././@PaxHeader";
+
+// 生成条目的 PAX 扩展记录
+pub(super) fn pax_records(path: &Path, metadata: &fs::Metadata) -> io::Result<Vec<u8>> {
+    let mut records = Vec::new();
+    if let Ok(mtime) = metadata.modified() {
+        push_time(&mut records, PAX_MTIME, mtime);
+    }
+    if let Ok(atime) = metadata.accessed() {
+        push_time(&mut records, PAX_ATIME, atime);
+    }
+    #[cfg(unix)]
+    for (name, value) in read_xattrs(path)? {
+        push_record(&mut records, &format!("{}{}", PAX_XATTR, name), &value);
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(records)
+}
+
+// 在下一个条目之前写入 PAX 扩展头
+pub(super) fn append_pax<W: Write>(builder: &mut tar::Builder<W>, records: &[u8]) -> io::Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_path(PAX_HEADER_NAME)?;
+    header.set_mode(0o644);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    builder.append(&header, records)
+}
+
+// 复制条目的 PAX 扩展记录。路径、链接目标和大小由新的 tar 头记录，不复制；ownership 为 false 时去掉属主
+pub(super) fn copy_pax<R: Read>(entry: &mut tar::Entry<R>, ownership: bool) -> io::Result<Vec<u8>> {
+    let mut records = Vec::new();
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(records);
+    };
+    for extension in extensions {
+        let extension = extension?;
+        let key = extension.key().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let skip = matches!(key, "path" | "linkpath" | "size")
+            || (!ownership && matches!(key, "uid" | "gid" | "uname" | "gname"));
+        if !skip {
+            push_record(&mut records, key, extension.value_bytes());
+        }
+    }
+    Ok(records)
+}
+
+// 每条记录为 "<len> <key>=<value>\n"，len 是整条记录的字节数（包括自身的位数）
+fn push_record(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{} {}=", len, key).as_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+// 早于 1970 年的时间只保留 tar 头中的值
+fn push_time(out: &mut Vec<u8>, key: &str, time: SystemTime) {
+    if let Ok(since_epoch) = time.duration_since(SystemTime::UNIX_EPOCH) {
+        let value = format!("{}.{:09}", since_epoch.as_secs(), since_epoch.subsec_nanos());
+        push_record(out, key, value.as_bytes());
+    }
+}
+
+pub(super) fn parse_time(value: &[u8]) -> Option<FileTime> {
+    let value = std::str::from_utf8(value).ok()?;
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let secs: u64 = secs.parse().ok()?;
+    let digits = &fraction[..fraction.len().min(9)];
+    let nanos = if digits.is_empty() { 0 } else { format!("{:0<9}", digits).parse().ok()? };
+    let time = SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+    Some(FileTime::from_system_time(time))
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(e) if ignorable(&e) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut attributes = Vec::new();
+    for name in names {
+        // 名称不是 UTF-8 的属性无法写入 PAX 记录，跳过
+        let Some(text) = name.to_str() else { continue };
+        match xattr::get(path, &name) {
+            Ok(Some(value)) => attributes.push((text.to_string(), value)),
+            Ok(None) => {}
+            Err(e) if ignorable(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    attributes.sort();
+    Ok(attributes)
+}
+
+#[cfg(unix)]
+fn ignorable(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied)
+}
+
+// 从条目中取出解压后需要补充还原的元数据
+#[derive(Default)]
+pub(super) struct Restore {
+    mtime: Option<FileTime>,
+    atime: Option<FileTime>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    owner: Option<(u64, u64)>,
+    mode: Option<u32>,
+    symlink: bool,
+}
+
+impl Restore {
+    pub fn from_entry<R: Read>(entry: &mut tar::Entry<R>, ownership: bool) -> io::Result<Self> {
+        let header = entry.header();
+        // tar 只还原文件和符号链接的修改时间；目录没有 PAX 记录时（如 zip、7z 转换来的条目）使用 tar 头中的时间
+        let directory_mtime = header
+            .entry_type()
+            .is_dir()
+            .then(|| header.mtime().ok())
+            .flatten()
+            .map(|mtime| FileTime::from_unix_time(mtime as i64, 0));
+        let mut restore = Restore {
+            mtime: directory_mtime,
+            owner: if ownership { header.uid().ok().zip(header.gid().ok()) } else { None },
+            mode: header.mode().ok(),
+            symlink: header.entry_type().is_symlink(),
+            ..Restore::default()
+        };
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension?;
+                let Ok(key) = extension.key() else { continue };
+                match key {
+                    PAX_MTIME => restore.mtime = parse_time(extension.value_bytes()).or(restore.mtime),
+                    PAX_ATIME => restore.atime = parse_time(extension.value_bytes()),
+                    _ => {
+                        if let Some(name) = key.strip_prefix(PAX_XATTR) {
+                            restore.xattrs.push((name.to_string(), extension.value_bytes().to_vec()));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(restore)
+    }
+
+    // 在 tar 解压出 path 之后调用
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        self.apply_unix(path)?;
+
+        // 时间最后设置，前面的修改不会再改动它；没有访问时间时与修改时间相同，和 tar 一致
+        if let Some(mtime) = self.mtime {
+            filetime::set_symlink_file_times(path, self.atime.unwrap_or(mtime), mtime)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn apply_unix(&self, path: &Path) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some((uid, gid)) = self.owner {
+            let (Ok(uid), Ok(gid)) = (u32::try_from(uid), u32::try_from(gid)) else {
+                return Ok(());
+            };
+            match std::os::unix::fs::lchown(path, Some(uid), Some(gid)) {
+                Ok(()) => {
+                    // 改属主会清掉 setuid/setgid 位，需要重新设置权限
+                    if let Some(mode) = self.mode.filter(|mode| !self.symlink && mode & 0o6000 != 0) {
+                        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+                Err(e) => return Err(e),
+            }
+        }
+        for (name, value) in &self.xattrs {
+            // 符号链接上的 user.* 属性在 Linux 上不允许设置，与其他无法还原的属性一样跳过
+            match xattr::set(path, name, value) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied && !self.symlink => {
+                    self.set_xattr_writable(path, name, value)?;
+                }
+                Err(e) if ignorable(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // 只读文件要先临时加上写权限才能设置扩展属性
+    #[cfg(unix)]
+    fn set_xattr_writable(&self, path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let permissions = fs::symlink_metadata(path)?.permissions();
+        if permissions.mode() & 0o200 != 0 {
+            return Ok(());
+        }
+        fs::set_permissions(path, fs::Permissions::from_mode(permissions.mode() | 0o200))?;
+        let result = xattr::set(path, name, value);
+        fs::set_permissions(path, permissions)?;
+        match result {
+            Err(e) if !ignorable(&e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 依次读取 tar 数据中各条目的待还原修改时间
+    fn restored_mtimes(records: &[u8]) -> Vec<Option<FileTime>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, kind) in [("docs", tar::EntryType::Directory), ("docs/readme.txt", tar::EntryType::Regular)] {
+            append_pax(&mut builder, records).unwrap();
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(kind);
+            header.set_mode(0o644);
+            header.set_mtime(1_600_000_000);
+            header.set_size(0);
+            builder.append_data(&mut header, path, io::empty()).unwrap();
+        }
+        let data = builder.into_inner().unwrap();
+        let mut archive = tar::Archive::new(data.as_slice());
+        let entries = archive.entries().unwrap();
+        entries.map(|entry| Restore::from_entry(&mut entry.unwrap(), false).unwrap().mtime).collect()
+    }
+
+    #[test]
+    fn directory_mtime_falls_back_to_tar_header() {
+        assert_eq!(restored_mtimes(b""), [Some(FileTime::from_unix_time(1_600_000_000, 0)), None]);
+
+        let mut records = Vec::new();
+        push_record(&mut records, PAX_MTIME, b"1600000000.5");
+        let exact = Some(FileTime::from_unix_time(1_600_000_000, 500_000_000));
+        assert_eq!(restored_mtimes(&records), [exact, exact]);
+    }
+}
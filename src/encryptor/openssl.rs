@@ -0,0 +1,280 @@
+// 旧版本调用 `openssl enc -aes-256-cbc -salt -pbkdf2` 生成的 .aes 文件，只读。
+//
+// ```text
+// "Salted__"（8 字节）+ 盐（8 字节）
+// AES-256-CBC 密文，PKCS#7 填充
+// ```
+//
+// 密钥和 IV 由 PBKDF2-HMAC-SHA256（10000 次迭代，openssl 的默认值）从密码和盐派生，共 48 字节，
+// 前 32 字节为密钥，后 16 字节为 IV。明文是 tar 数据。
+//
+// 这个格式没有认证：密码是否正确只能从最后一块的填充和 tar 头推断，内容被修改也无法发现。
+// CBC 解密时每块只依赖前一块密文，因此同样可以定位到任意位置读取。
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use aes::Aes256;
+use aes::cipher::block_padding::{NoPadding, Pkcs7};
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use sha2::Sha256;
+
+use super::error::EncryptorError;
+use super::kdf::Kdf;
+
+type Decryptor = cbc::Decryptor<Aes256>;
+
+const MAGIC: &[u8; 8] = b"Salted__";
+const SALT_LEN: usize = 8;
+const KEY_LEN: usize = 32;
+const BLOCK_LEN: usize = 16;
+// 每次解密的明文长度，是块长度的整数倍
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAR_BLOCK: usize = 512;
+
+// openssl enc -pbkdf2 的默认迭代次数
+const ITERATIONS: u32 = 10000;
+pub(super) const KDF: Kdf = Kdf::Pbkdf2Sha256 { iterations: ITERATIONS };
+
+// 文件开头是否为 openssl 的盐头部；读取后回到原位置
+pub(super) fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let start = reader.stream_position()?;
+    let mut magic = Vec::new();
+    reader.by_ref().take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(magic == MAGIC)
+}
+
+// 可随机访问的 CBC 解密读取器，按 CHUNK_SIZE 缓存解密结果
+pub(super) struct CbcReader<R: Read + Seek> {
+    inner: R,
+    key: [u8; KEY_LEN],
+    iv: [u8; BLOCK_LEN],
+    start: u64,
+    plain_len: u64,
+    loaded: Option<u64>,
+    plaintext: Vec<u8>,
+    position: u64,
+}
+
+impl<R: Read + Seek> CbcReader<R> {
+    // 读取盐并派生密钥。最后一块的填充无效或开头不是 tar 头时按密码错误处理
+    pub fn open(mut inner: R, password: &str) -> Result<Self, EncryptorError> {
+        let read_error = EncryptorError::io("Failed to read encrypted file");
+        let mut head = [0u8; MAGIC.len() + SALT_LEN];
+        inner.read_exact(&mut head).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => EncryptorError::Corrupted,
+            _ => read_error(e),
+        })?;
+        if &head[..MAGIC.len()] != MAGIC {
+            return Err(EncryptorError::UnsupportedFormat("missing OpenSSL salt header".to_string()));
+        }
+
+        let mut derived = [0u8; KEY_LEN + BLOCK_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &head[MAGIC.len()..], ITERATIONS, &mut derived);
+        let start = head.len() as u64;
+        let end = inner.seek(SeekFrom::End(0)).map_err(read_error)?;
+        let encrypted_len = end - start;
+        if encrypted_len == 0 || !encrypted_len.is_multiple_of(BLOCK_LEN as u64) {
+            return Err(EncryptorError::Corrupted);
+        }
+
+        let mut reader = CbcReader {
+            inner,
+            key: derived[..KEY_LEN].try_into().unwrap(),
+            iv: derived[KEY_LEN..].try_into().unwrap(),
+            start,
+            plain_len: encrypted_len,
+            loaded: None,
+            plaintext: Vec::new(),
+            position: 0,
+        };
+
+        // 去掉填充后才知道明文长度
+        let last = encrypted_len - BLOCK_LEN as u64;
+        let mut block = [0u8; BLOCK_LEN];
+        let previous = reader.previous_block(last).map_err(read_error)?;
+        reader.inner.read_exact(&mut block).map_err(read_error)?;
+        let padding = match Decryptor::new(&reader.key.into(), &previous.into()).decrypt_padded_mut::<Pkcs7>(&mut block) {
+            Ok(unpadded) => BLOCK_LEN - unpadded.len(),
+            Err(_) => return Err(EncryptorError::WrongPassword),
+        };
+        reader.plain_len = encrypted_len - padding as u64;
+
+        let mut tar_header = [0u8; TAR_BLOCK];
+        if reader.plain_len < TAR_BLOCK as u64 {
+            return Err(EncryptorError::WrongPassword);
+        }
+        reader.read_exact(&mut tar_header).map_err(read_error)?;
+        if &tar_header[257..262] != b"ustar" {
+            return Err(EncryptorError::WrongPassword);
+        }
+        reader.position = 0;
+        Ok(reader)
+    }
+
+    // 明文总长度
+    pub fn plain_len(&self) -> u64 {
+        self.plain_len
+    }
+
+    // 密文中 offset 处的块之前的一块（第一块之前为 IV），同时把 inner 定位到 offset
+    fn previous_block(&mut self, offset: u64) -> io::Result<[u8; BLOCK_LEN]> {
+        if offset == 0 {
+            self.inner.seek(SeekFrom::Start(self.start))?;
+            return Ok(self.iv);
+        }
+        let mut block = [0u8; BLOCK_LEN];
+        self.inner.seek(SeekFrom::Start(self.start + offset - BLOCK_LEN as u64))?;
+        self.inner.read_exact(&mut block)?;
+        Ok(block)
+    }
+
+    fn load_chunk(&mut self, index: u64) -> io::Result<()> {
+        if self.loaded == Some(index) {
+            return Ok(());
+        }
+
+        let offset = index * CHUNK_SIZE as u64;
+        let previous = self.previous_block(offset)?;
+        // 读到最后一块为止，填充在解密后截掉
+        let encrypted_len = self.plain_len.next_multiple_of(BLOCK_LEN as u64);
+        let len = (encrypted_len - offset).min(CHUNK_SIZE as u64) as usize;
+        let mut buffer = vec![0u8; len];
+        self.inner.read_exact(&mut buffer)?;
+        Decryptor::new(&self.key.into(), &previous.into())
+            .decrypt_padded_mut::<NoPadding>(&mut buffer)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "ciphertext is not a whole number of blocks"))?;
+        buffer.truncate((self.plain_len - offset).min(len as u64) as usize);
+
+        self.plaintext = buffer;
+        self.loaded = Some(index);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for CbcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.plain_len {
+            return Ok(0);
+        }
+
+        let index = self.position / CHUNK_SIZE as u64;
+        self.load_chunk(index)?;
+
+        let offset = (self.position % CHUNK_SIZE as u64) as usize;
+        let available = &self.plaintext[offset..];
+        let take = available.len().min(buf.len());
+        buf[..take].copy_from_slice(&available[..take]);
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for CbcReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use sha2::Digest;
+
+    use super::*;
+    use crate::encryptor::testutil::{fixture, hex, tar_items};
+    use crate::encryptor::{self, DecryptOptions, EncryptOptions, UpgradeOptions};
+
+    // 由 openssl enc -aes-256-cbc -salt -pbkdf2 生成，密码为 "secret"
+    const FIXTURE: &str = "openssl/legacy.aes";
+
+    fn open(data: Vec<u8>, password: &str) -> Result<CbcReader<Cursor<Vec<u8>>>, EncryptorError> {
+        CbcReader::open(Cursor::new(data), password)
+    }
+
+    #[test]
+    fn reads_openssl_file() {
+        assert!(detect(&mut Cursor::new(fixture(FIXTURE))).unwrap());
+        let items = tar_items(open(fixture(FIXTURE), "secret").unwrap());
+        let names: Vec<_> = items.iter().map(|(path, kind, _)| (path.as_str(), *kind)).collect();
+        assert_eq!(
+            names,
+            [
+                ("legacy", tar::EntryType::Directory),
+                ("legacy/data.bin", tar::EntryType::Regular),
+                ("legacy/docs", tar::EntryType::Directory),
+                ("legacy/docs/hello.txt", tar::EntryType::Regular),
+            ]
+        );
+        assert_eq!(sha2::Sha256::digest(&items[1].2)[..], hex("fcfcbc469957529c56241148d1075b4385850e1c5e8434dbefa0f0270c855750"));
+        assert_eq!(items[3].2, b"hello from openssl\n");
+    }
+
+    #[test]
+    fn seeks_within_plaintext() {
+        let mut reader = open(fixture(FIXTURE), "secret").unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all.len() as u64, reader.plain_len());
+
+        for position in [0, 15, 16, 1000, all.len() as u64 - 7] {
+            let mut part = [0u8; 7];
+            reader.seek(SeekFrom::Start(position)).unwrap();
+            reader.read_exact(&mut part).unwrap();
+            assert_eq!(part, all[position as usize..][..7]);
+        }
+        reader.seek(SeekFrom::End(-3)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, all[all.len() - 3..]);
+    }
+
+    // 错误的密码多数情况下填充无效，偶尔填充碰巧有效时由 tar 头发现；都报密码错误，不会 panic
+    #[test]
+    fn rejects_wrong_passwords() {
+        for n in 0..16 {
+            let password = format!("wrong {}", n);
+            assert!(matches!(open(fixture(FIXTURE), &password), Err(EncryptorError::WrongPassword)), "{}", password);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = fixture(FIXTURE);
+        for len in [4, MAGIC.len() + SALT_LEN, data.len() - 5] {
+            assert!(matches!(open(data[..len].to_vec(), "secret"), Err(EncryptorError::Corrupted)), "{}", len);
+        }
+    }
+
+    #[test]
+    fn upgrade_converts_to_current_format() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("legacy.aes");
+        fs::write(&path, fixture(FIXTURE)).unwrap();
+
+        let encrypt = EncryptOptions {
+            kdf: Kdf::Argon2id { memory_kib: 8 * 1024, iterations: 1, parallelism: 1 },
+            ..Default::default()
+        };
+        let report = encryptor::upgrade_archive(&path, "secret", &UpgradeOptions { encrypt, ..Default::default() }).unwrap();
+        assert_eq!(report.output, path);
+        assert_eq!(report.entries, 4);
+        assert!(!detect(&mut fs::File::open(&path).unwrap()).unwrap());
+
+        let options = DecryptOptions { destination: Some(temp.path().join("out")), ..Default::default() };
+        encryptor::decrypt_folder(&path, "secret", &options).unwrap();
+        let extracted = temp.path().join("out/legacy");
+        assert_eq!(fs::read(extracted.join("docs/hello.txt")).unwrap(), b"hello from openssl\n");
+        assert_eq!(sha2::Sha256::digest(fs::read(extracted.join("data.bin")).unwrap())[..], hex("fcfcbc469957529c56241148d1075b4385850e1c5e8434dbefa0f0270c855750"));
+        assert!(matches!(encryptor::decrypt_folder(&path, "wrong", &options), Err(EncryptorError::WrongPassword)));
+    }
+}
@@ -0,0 +1,217 @@
+// 输出先写到同目录下的临时名称，任务成功后再按冲突策略移动到最终位置，
+// 这样中途失败、取消或进程被杀都不会留下写了一半的目标文件。
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rand::RngCore;
+
+use super::error::EncryptorError;
+
+// 目标位置已存在时的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // 返回 EncryptorError::OutputExists，不做任何修改
+    #[default]
+    Fail,
+    // 改用 "name (1)"、"name (2)" 等未被占用的名称
+    Rename,
+    // 替换已有的文件或文件夹
+    Overwrite,
+    // 解密时合并到已有文件夹中，同名文件被替换；加密时不可用
+    Merge,
+}
+
+// 同目录下的临时名称，以点开头以免在文件管理器中出现
+pub fn temp_path(parent: &Path) -> PathBuf {
+    let mut suffix = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut suffix);
+    let suffix: String = suffix.iter().map(|byte| format!("{:02x}", byte)).collect();
+    parent.join(format!(".pw-tmp-{}", suffix))
+}
+
+fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+// 按策略确定最终路径；Fail 策略下目标已存在时返回错误
+pub fn resolve(target: &Path, policy: ConflictPolicy) -> Result<PathBuf, EncryptorError> {
+    if !exists(target) {
+        return Ok(target.to_path_buf());
+    }
+    match policy {
+        ConflictPolicy::Fail => Err(EncryptorError::OutputExists(target.to_path_buf())),
+        ConflictPolicy::Overwrite | ConflictPolicy::Merge => Ok(target.to_path_buf()),
+        ConflictPolicy::Rename => {
+            let parent = target.parent().unwrap_or(Path::new(""));
+            let (stem, extension) = match (target.file_stem(), target.extension()) {
+                // 文件夹名中的点不是扩展名，只对文件拆分
+                (Some(stem), Some(extension)) if !target.is_dir() => (stem.to_os_string(), Some(extension)),
+                _ => (target.file_name().unwrap_or_default().to_os_string(), None),
+            };
+            (1..)
+                .map(|n| {
+                    let mut name = OsString::from(&stem);
+                    name.push(format!(" ({})", n));
+                    if let Some(extension) = extension {
+                        name.push(".");
+                        name.push(extension);
+                    }
+                    parent.join(name)
+                })
+                .find(|candidate| !exists(candidate))
+                .ok_or_else(|| EncryptorError::OutputExists(target.to_path_buf()))
+        }
+    }
+}
+
+// 把临时输出移动到 target（已由 resolve 确定）。
+// 覆盖已有文件夹时先把旧文件夹改名，新内容就位后再删除，避免中途失败时两者都丢失。
+pub fn persist(temp: &Path, target: &Path, policy: ConflictPolicy) -> io::Result<()> {
+    let Ok(existing) = fs::symlink_metadata(target) else {
+        return fs::rename(temp, target);
+    };
+    let incoming = fs::symlink_metadata(temp)?;
+
+    if policy == ConflictPolicy::Merge && existing.is_dir() && incoming.is_dir() {
+        for child in fs::read_dir(temp)? {
+            let child = child?;
+            persist(&child.path(), &target.join(child.file_name()), policy)?;
+        }
+        return fs::remove_dir(temp);
+    }
+
+    if existing.is_dir() {
+        let backup = temp_path(target.parent().unwrap_or(Path::new(".")));
+        fs::rename(target, &backup)?;
+        if let Err(e) = fs::rename(temp, target) {
+            let _ = fs::rename(&backup, target);
+            return Err(e);
+        }
+        return fs::remove_dir_all(&backup);
+    }
+    if incoming.is_dir() {
+        fs::remove_file(target)?;
+    }
+    fs::rename(temp, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryptor::{self, DecryptOptions, EncryptOptions, Kdf};
+
+    // 目录中残留的临时文件和文件夹
+    fn leftovers(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with(".pw-tmp-"))
+            .collect()
+    }
+
+    // 在 parent 下写一个临时文件夹，其中为给定的文件
+    fn staged(parent: &Path, files: &[(&str, &str)]) -> PathBuf {
+        let temp = temp_path(parent);
+        for (name, content) in files {
+            let path = temp.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        temp
+    }
+
+    #[test]
+    fn fail_keeps_existing_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("out.aes");
+        assert_eq!(resolve(&target, ConflictPolicy::Fail).unwrap(), target);
+        fs::write(&target, "old").unwrap();
+        assert!(matches!(resolve(&target, ConflictPolicy::Fail), Err(EncryptorError::OutputExists(path)) if path == target));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old");
+    }
+
+    #[test]
+    fn rename_picks_an_unused_name() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("out.aes"), "old").unwrap();
+        fs::write(dir.path().join("out (1).aes"), "old").unwrap();
+        let target = resolve(&dir.path().join("out.aes"), ConflictPolicy::Rename).unwrap();
+        assert_eq!(target, dir.path().join("out (2).aes"));
+
+        // 文件夹名中的点不当作扩展名
+        fs::create_dir(dir.path().join("photos.2024")).unwrap();
+        let folder = resolve(&dir.path().join("photos.2024"), ConflictPolicy::Rename).unwrap();
+        assert_eq!(folder, dir.path().join("photos.2024 (1)"));
+        persist(&staged(dir.path(), &[("a.txt", "new")]), &folder, ConflictPolicy::Rename).unwrap();
+        assert_eq!(fs::read_to_string(folder.join("a.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.path().join("out.aes")).unwrap(), "old");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn overwrite_replaces_files_and_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("docs");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("old.txt"), "old").unwrap();
+        assert_eq!(resolve(&folder, ConflictPolicy::Overwrite).unwrap(), folder);
+        persist(&staged(dir.path(), &[("new.txt", "new")]), &folder, ConflictPolicy::Overwrite).unwrap();
+        assert!(!folder.join("old.txt").exists());
+        assert_eq!(fs::read_to_string(folder.join("new.txt")).unwrap(), "new");
+
+        // 文件被文件夹替换
+        let file = dir.path().join("notes");
+        fs::write(&file, "old").unwrap();
+        persist(&staged(dir.path(), &[("a.txt", "new")]), &file, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(fs::read_to_string(file.join("a.txt")).unwrap(), "new");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn merge_combines_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("docs");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("a.txt"), "old").unwrap();
+        fs::write(folder.join("keep.txt"), "keep").unwrap();
+        assert_eq!(resolve(&folder, ConflictPolicy::Merge).unwrap(), folder);
+        persist(&staged(dir.path(), &[("a.txt", "new"), ("sub/b.txt", "new")]), &folder, ConflictPolicy::Merge).unwrap();
+        assert_eq!(fs::read_to_string(folder.join("a.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(folder.join("keep.txt")).unwrap(), "keep");
+        assert_eq!(fs::read_to_string(folder.join("sub/b.txt")).unwrap(), "new");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    // 目标已存在或归档损坏时，加密、解密失败后不留下临时输出
+    #[test]
+    fn failures_remove_temporary_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("docs");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("a.txt"), "hello").unwrap();
+        let encrypt = EncryptOptions {
+            kdf: Kdf::Argon2id { memory_kib: 8 * 1024, iterations: 1, parallelism: 1 },
+            ..Default::default()
+        };
+        let archive = encryptor::encrypt_folder(&source, "secret", &encrypt).unwrap().output;
+        assert!(matches!(encryptor::encrypt_folder(&source, "secret", &encrypt), Err(EncryptorError::OutputExists(_))));
+
+        // 顶层文件夹 docs 已存在
+        let decrypt = DecryptOptions::default();
+        assert!(matches!(encryptor::decrypt_folder(&archive, "secret", &decrypt), Err(EncryptorError::OutputExists(_))));
+
+        // 解压到一半发现数据被改动
+        let mut data = fs::read(&archive).unwrap();
+        let middle = data.len() - 100;
+        data[middle] ^= 1;
+        fs::write(&archive, data).unwrap();
+        let decrypt = DecryptOptions { conflict: ConflictPolicy::Rename, ..Default::default() };
+        assert!(encryptor::decrypt_folder(&archive, "secret", &decrypt).is_err());
+
+        assert!(leftovers(dir.path()).is_empty());
+        assert!(!dir.path().join("docs (1)").exists());
+    }
+}
@@ -0,0 +1,234 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::cancel::CancelToken;
+use super::error::EncryptorError;
+
+// 进度回调，在工作线程中调用，实现需要自行处理线程同步
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+// 两次上报之间的最小间隔，避免大量小文件或大文件逐块读写时频繁回调
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+// 任务所处的阶段
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    // 遍历文件夹，统计条目数和总字节数
+    Scan,
+    // 打包文件，打包结果同时分块加密写出
+    Archive,
+    // 写出最后一块密文和条目索引
+    Encrypt,
+    // 收尾：写 footer、设置目录属性等
+    Finalize,
+    // 解密并解压
+    Extract,
+    // 解密并校验，不写文件
+    Verify,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::Scan => "Scanning",
+            Phase::Archive => "Archiving",
+            Phase::Encrypt => "Encrypting",
+            Phase::Finalize => "Finalizing",
+            Phase::Extract => "Extracting",
+            Phase::Verify => "Verifying",
+        }
+    }
+}
+
+// 进度事件。阶段切换时一定会上报，其余情况按 REPORT_INTERVAL 节流
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Progress {
+    pub phase: Phase,
+    // 扫描阶段已发现的条目数
+    pub scanned: usize,
+    // 已处理的条目数
+    pub entries: usize,
+    pub total_entries: Option<usize>,
+    // 已处理的字节数：加密时为文件内容，解密时为解密出的 tar 数据
+    pub bytes: u64,
+    pub total_bytes: Option<u64>,
+    // 正在处理的条目在归档中的路径
+    pub current: String,
+    // 当前阶段已用时间，用于计算速度和剩余时间
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    // 完成比例（0.0..=1.0），总量未知时为 None
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total_bytes {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.bytes as f64 / total as f64).min(1.0) as f32),
+            None => None,
+        }
+    }
+
+    // 当前阶段的平均速度（字节/秒）
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.elapsed.as_secs_f64();
+        (secs > 0.0 && self.bytes > 0).then(|| self.bytes as f64 / secs)
+    }
+
+    // 按平均速度估算的剩余时间
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_bytes?.saturating_sub(self.bytes);
+        Duration::try_from_secs_f64(remaining as f64 / self.throughput()?).ok()
+    }
+}
+
+// 在一次任务中累计进度并按需调用回调，同时负责检查取消请求。
+// 使用内部可变性，这样读取数据的包装器和遍历条目的循环可以同时持有它。
+pub(super) struct Tracker<'a> {
+    callback: Option<&'a ProgressCallback>,
+    cancel: Option<&'a CancelToken>,
+    progress: RefCell<Progress>,
+    phase_started: Cell<Instant>,
+    last_report: Cell<Option<Instant>>,
+}
+
+impl<'a> Tracker<'a> {
+    pub fn new(callback: Option<&'a ProgressCallback>, cancel: Option<&'a CancelToken>, phase: Phase) -> Self {
+        let tracker = Tracker {
+            callback,
+            cancel,
+            progress: RefCell::new(Progress {
+                phase,
+                scanned: 0,
+                entries: 0,
+                total_entries: None,
+                bytes: 0,
+                total_bytes: None,
+                current: String::new(),
+                elapsed: Duration::ZERO,
+            }),
+            phase_started: Cell::new(Instant::now()),
+            last_report: Cell::new(None),
+        };
+        tracker.report(true);
+        tracker
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    // 已取消时返回错误，让正在进行的读写尽快停下
+    pub fn check(&self) -> io::Result<()> {
+        if self.cancelled() {
+            return Err(io::Error::other("operation cancelled"));
+        }
+        Ok(())
+    }
+
+    // 用于 map_err：取消导致的错误报告为 Cancelled，其余附加步骤说明
+    pub fn error<'c>(&'c self, context: &'c str) -> impl Fn(io::Error) -> EncryptorError + Copy + 'c {
+        move |source| {
+            if self.cancelled() {
+                EncryptorError::Cancelled
+            } else {
+                EncryptorError::io(context)(source)
+            }
+        }
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        self.progress.borrow_mut().phase = phase;
+        self.phase_started.set(Instant::now());
+        self.report(true);
+    }
+
+    pub fn set_totals(&self, entries: Option<usize>, bytes: Option<u64>) {
+        let mut progress = self.progress.borrow_mut();
+        progress.total_entries = entries;
+        progress.total_bytes = bytes;
+    }
+
+    pub fn scanned(&self, bytes: u64) -> io::Result<()> {
+        self.check()?;
+        let mut progress = self.progress.borrow_mut();
+        progress.scanned += 1;
+        progress.total_entries = Some(progress.scanned);
+        progress.total_bytes = Some(progress.total_bytes.unwrap_or(0) + bytes);
+        drop(progress);
+        self.report(false);
+        Ok(())
+    }
+
+    pub fn start_entry(&self, path: &str) -> io::Result<()> {
+        self.check()?;
+        self.progress.borrow_mut().current = path.to_string();
+        self.report(false);
+        Ok(())
+    }
+
+    pub fn finish_entry(&self) {
+        self.progress.borrow_mut().entries += 1;
+        self.report(false);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.progress.borrow_mut().bytes += bytes;
+        self.report(false);
+    }
+
+    fn report(&self, force: bool) {
+        let Some(callback) = self.callback else {
+            return;
+        };
+        let now = Instant::now();
+        if !force && self.last_report.get().is_some_and(|last| now - last < REPORT_INTERVAL) {
+            return;
+        }
+        self.last_report.set(Some(now));
+
+        let mut progress = self.progress.borrow().clone();
+        progress.elapsed = now - self.phase_started.get();
+        callback(&progress);
+    }
+}
+
+// 读取时把字节数计入进度
+pub(super) struct Counting<'t, 'a, R> {
+    inner: R,
+    tracker: &'t Tracker<'a>,
+}
+
+impl<'t, 'a, R> Counting<'t, 'a, R> {
+    pub fn new(inner: R, tracker: &'t Tracker<'a>) -> Self {
+        Counting { inner, tracker }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Counting<'_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.tracker.check()?;
+        let n = self.inner.read(buf)?;
+        self.tracker.add_bytes(n as u64);
+        Ok(n)
+    }
+}
+
+// 选择性解压时跳过未选中条目的内容，跳过的字节不计入进度
+impl<R: Seek> Seek for Counting<'_, '_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.tracker.check()?;
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,199 @@
+// 选择性解压：按归档路径或通配符挑选条目。
+//
+// 模式按 "/" 分段匹配："*" 匹配段内任意字符，"?" 匹配段内单个字符，"**" 匹配任意多段。
+// 选中一个文件夹即选中其下的全部内容。以 "!" 开头的模式排除匹配的条目及其下的内容，
+// 优先于选中的模式；只有排除模式时选中其余全部内容。
+
+use std::cell::Cell;
+
+pub(super) struct Selection {
+    patterns: Vec<String>,
+    // 每个模式是否至少匹配过一个条目，用于报告拼写错误的路径
+    matched: Vec<Cell<bool>>,
+    // 排除模式（去掉了开头的 "!"）；没有匹配任何条目不算错误
+    excludes: Vec<String>,
+}
+
+impl Selection {
+    // 没有模式时返回 None，表示解压全部内容
+    pub fn new(patterns: &[String]) -> Option<Self> {
+        if patterns.is_empty() {
+            return None;
+        }
+        let (excludes, patterns): (Vec<&String>, Vec<&String>) = patterns.iter().partition(|pattern| pattern.starts_with('!'));
+        let patterns: Vec<String> = patterns.iter().map(|pattern| normalize(pattern).to_string()).collect();
+        let excludes = excludes.iter().map(|pattern| normalize(&pattern[1..]).to_string()).collect();
+        let matched = patterns.iter().map(|_| Cell::new(false)).collect();
+        Some(Selection { patterns, matched, excludes })
+    }
+
+    // 条目本身或它的某个上级文件夹被选中，且都没有被排除
+    pub fn matches(&self, path: &str) -> bool {
+        let path = normalize(path);
+        let mut selected = self.patterns.is_empty();
+        for (pattern, matched) in self.patterns.iter().zip(&self.matched) {
+            if hits(pattern, path) {
+                matched.set(true);
+                selected = true;
+            }
+        }
+        selected && !self.excludes.iter().any(|pattern| hits(pattern, path))
+    }
+
+    // 没有匹配任何条目的第一个模式
+    pub fn unmatched(&self) -> Option<&str> {
+        self.patterns
+            .iter()
+            .zip(&self.matched)
+            .find(|(_, matched)| !matched.get())
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+// 去掉开头的 "./" 和结尾的 "/"，旧格式中的目录条目以 "/" 结尾
+fn normalize(path: &str) -> &str {
+    let path = path.trim_start_matches("./");
+    path.trim_end_matches('/')
+}
+
+fn hits(pattern: &str, path: &str) -> bool {
+    ancestors(path).any(|candidate| candidate == pattern || glob_match(pattern, candidate))
+}
+
+// path 本身及其各级上级，如 "a/b/c"、"a/b"、"a"
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(path).chain(path.rmatch_indices('/').map(move |(index, _)| &path[..index]))
+}
+
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern, &path)
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => match_segment(first, segment) && match_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+// 单段内的 "*" 和 "?" 匹配，遇到 "*" 时记录回溯点
+fn match_segment(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, consumed)) => {
+                    p = star + 1;
+                    t = consumed + 1;
+                    backtrack = Some((star, consumed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(patterns: &[&str]) -> Selection {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        Selection::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn empty_selects_everything() {
+        assert!(Selection::new(&[]).is_none());
+    }
+
+    #[test]
+    fn matches_globs() {
+        let selected = selection(&["*.txt", "img/??.png", "src/**/mod.rs"]);
+        assert!(selected.matches("notes.txt"));
+        assert!(selected.matches(".txt"));
+        assert!(!selected.matches("notes.txt.bak"));
+        // "*" 不跨越 "/"
+        assert!(!selected.matches("docs/notes.txt"));
+        assert!(selected.matches("img/ab.png"));
+        assert!(!selected.matches("img/abc.png"));
+        assert!(!selected.matches("img/a.png"));
+        // "**" 匹配零段或多段
+        assert!(selected.matches("src/mod.rs"));
+        assert!(selected.matches("src/a/b/mod.rs"));
+        assert!(!selected.matches("lib/a/mod.rs"));
+
+        assert!(match_segment("a*b*c", "axxbyyc"));
+        assert!(!match_segment("a*b*c", "axxbyy"));
+        assert!(match_segment("**", ""));
+        assert!(selection(&["**"]).matches("any/depth/file"));
+    }
+
+    #[test]
+    fn folders_select_their_contents() {
+        let selected = selection(&["./docs/"]);
+        assert!(selected.matches("docs"));
+        assert!(selected.matches("docs/"));
+        assert!(selected.matches("docs/a/b.txt"));
+        assert!(selected.matches("./docs/a.txt"));
+        // 只按整段匹配，不是字符串前缀
+        assert!(!selected.matches("docs2/a.txt"));
+        assert!(!selected.matches("other/docs/a.txt"));
+
+        // 通配符匹配的文件夹同样包括其内容
+        let selected = selection(&["photos/20??"]);
+        assert!(selected.matches("photos/2024/a.jpg"));
+        assert!(!selected.matches("photos/1999/a.jpg"));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let selected = selection(&["docs", "!docs/drafts", "!**/*.tmp"]);
+        assert!(selected.matches("docs/a.txt"));
+        assert!(!selected.matches("docs/drafts"));
+        assert!(!selected.matches("docs/drafts/a.txt"));
+        assert!(!selected.matches("docs/b.tmp"));
+        assert!(!selected.matches("readme.txt"));
+        // 同一路径既被选中又被排除时排除
+        assert!(!selection(&["a.txt", "!a.txt"]).matches("a.txt"));
+    }
+
+    #[test]
+    fn excludes_alone_select_the_rest() {
+        let selected = selection(&["!cache", "!*.log"]);
+        assert!(selected.matches("docs/a.txt"));
+        assert!(selected.matches("docs/b.log"));
+        assert!(!selected.matches("cache/x"));
+        assert!(!selected.matches("b.log"));
+        assert_eq!(selected.unmatched(), None);
+    }
+
+    #[test]
+    fn reports_unmatched_patterns() {
+        let selected = selection(&["docs", "missing/*", "!nothing"]);
+        selected.matches("docs/a.txt");
+        selected.matches("src/main.rs");
+        assert_eq!(selected.unmatched(), Some("missing/*"));
+        // 被排除的条目仍算作匹配了选中的模式
+        selected.matches("missing/a");
+        assert_eq!(selected.unmatched(), None);
+    }
+}
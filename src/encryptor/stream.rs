@@ -0,0 +1,310 @@
+// 分块认证加密（STREAM 构造）
+//
+// 明文按 CHUNK_SIZE 切块，每块独立加密并带 16 字节认证标签。
+// nonce = 19 字节前缀 + 4 字节块序号（大端）+ 1 字节结尾标记，
+// 结尾标记保证截断在块边界上的文件也能被发现。
+// 除最后一块外每块密文长度固定，因此可以直接定位到任意块单独解密。
+//
+// age 文件的载荷使用同样的分块方式，只是算法为 ChaCha20-Poly1305，
+// nonce = 11 字节块序号（大端）+ 1 字节结尾标记，没有附加数据。
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, XChaCha20Poly1305, XNonce};
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+pub const NONCE_PREFIX_LEN: usize = 19;
+const TAG_LEN: usize = 16;
+const ENCRYPTED_CHUNK_SIZE: usize = CHUNK_SIZE + TAG_LEN;
+
+// 每块的加密算法和 nonce 构造
+enum ChunkCipher {
+    Native { cipher: XChaCha20Poly1305, prefix: [u8; NONCE_PREFIX_LEN], aad: Vec<u8> },
+    Age(ChaCha20Poly1305),
+}
+
+impl ChunkCipher {
+    fn encrypt(&self, counter: u32, last: bool, msg: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            ChunkCipher::Native { cipher, prefix, aad } => cipher.encrypt(&native_nonce(prefix, counter, last), Payload { msg, aad }).ok(),
+            ChunkCipher::Age(cipher) => cipher.encrypt(&age_nonce(counter, last), msg).ok(),
+        }
+    }
+
+    fn decrypt(&self, counter: u32, last: bool, msg: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            ChunkCipher::Native { cipher, prefix, aad } => cipher.decrypt(&native_nonce(prefix, counter, last), Payload { msg, aad }).ok(),
+            ChunkCipher::Age(cipher) => cipher.decrypt(&age_nonce(counter, last), msg).ok(),
+        }
+    }
+}
+
+fn native_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32, last: bool) -> XNonce {
+    let mut nonce = XNonce::default();
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_PREFIX_LEN + 4].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_PREFIX_LEN + 4] = last as u8;
+    nonce
+}
+
+// 块序号不超过 u32，11 字节序号的高位总为 0
+fn age_nonce(counter: u32, last: bool) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    cipher: ChunkCipher,
+    counter: u32,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(inner: W, key: &Key, prefix: [u8; NONCE_PREFIX_LEN], aad: Vec<u8>) -> Self {
+        Self::with_cipher(inner, ChunkCipher::Native { cipher: XChaCha20Poly1305::new(key), prefix, aad })
+    }
+
+    // age 文件的载荷
+    pub fn age(inner: W, key: &Key) -> Self {
+        Self::with_cipher(inner, ChunkCipher::Age(ChaCha20Poly1305::new(key)))
+    }
+
+    fn with_cipher(inner: W, cipher: ChunkCipher) -> Self {
+        Self {
+            inner,
+            cipher,
+            counter: 0,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let ciphertext = self.cipher
+            .encrypt(self.counter, last, &self.buffer)
+            .ok_or_else(|| io::Error::other("failed to encrypt chunk"))?;
+        self.inner.write_all(&ciphertext)?;
+
+        self.buffer.clear();
+        self.counter = self.counter.checked_add(1)
+            .ok_or_else(|| io::Error::other("archive is too large"))?;
+        Ok(())
+    }
+
+    // 写入最后一块（可能为空）并返回底层 writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        // 缓冲区满且还有后续数据时才写出，这样最后一块总是由 finish 写出
+        if self.buffer.len() == CHUNK_SIZE {
+            self.write_chunk(false)?;
+        }
+        let take = data.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..take]);
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 可随机访问的解密读取器：只解密实际读到的块。
+// 载荷位于 inner 的 [start, end) 区间，块数和最后一块长度由区间长度推出。
+pub struct ChunkReader<R: Read + Seek> {
+    inner: R,
+    cipher: ChunkCipher,
+    start: u64,
+    chunk_count: u64,
+    last_chunk_len: usize,
+    plain_len: u64,
+    inner_position: Option<u64>,
+    loaded: Option<u64>,
+    plaintext: Vec<u8>,
+    position: u64,
+    // 最后一块是否已通过认证；读到末尾时必须确认，否则截断的载荷也能读完
+    end_verified: bool,
+    auth_failed: bool,
+}
+
+impl<R: Read + Seek> ChunkReader<R> {
+    pub fn new(inner: R, key: &Key, prefix: [u8; NONCE_PREFIX_LEN], aad: Vec<u8>, start: u64, end: u64) -> Self {
+        Self::with_cipher(inner, ChunkCipher::Native { cipher: XChaCha20Poly1305::new(key), prefix, aad }, start, end)
+    }
+
+    // age 文件的载荷
+    pub fn age(inner: R, key: &Key, start: u64, end: u64) -> Self {
+        Self::with_cipher(inner, ChunkCipher::Age(ChaCha20Poly1305::new(key)), start, end)
+    }
+
+    fn with_cipher(inner: R, cipher: ChunkCipher, start: u64, end: u64) -> Self {
+        let encrypted_len = end.saturating_sub(start);
+        let chunk_count = encrypted_len.div_ceil(ENCRYPTED_CHUNK_SIZE as u64).max(1);
+        let last_chunk_len = (encrypted_len - (chunk_count - 1) * ENCRYPTED_CHUNK_SIZE as u64) as usize;
+        let plain_len = (chunk_count - 1) * CHUNK_SIZE as u64 + last_chunk_len.saturating_sub(TAG_LEN) as u64;
+
+        Self {
+            inner,
+            cipher,
+            start,
+            chunk_count,
+            last_chunk_len,
+            plain_len,
+            inner_position: None,
+            loaded: None,
+            plaintext: Vec::new(),
+            position: 0,
+            end_verified: false,
+            auth_failed: false,
+        }
+    }
+
+    // 明文总长度
+    pub fn plain_len(&self) -> u64 {
+        self.plain_len
+    }
+
+    // 认证失败时底层错误会被 tar 等上层包装，调用方通过这里判断是否为密文损坏
+    pub fn auth_failed(&self) -> bool {
+        self.auth_failed
+    }
+
+    pub fn take_auth_failed(&mut self) -> bool {
+        std::mem::take(&mut self.auth_failed)
+    }
+
+    fn corrupted(&mut self) -> io::Error {
+        self.auth_failed = true;
+        io::Error::new(io::ErrorKind::InvalidData, "chunk authentication failed")
+    }
+
+    fn load_chunk(&mut self, index: u64) -> io::Result<()> {
+        if self.loaded == Some(index) {
+            return Ok(());
+        }
+
+        let last = index + 1 == self.chunk_count;
+        let len = if last { self.last_chunk_len } else { ENCRYPTED_CHUNK_SIZE };
+        let counter = u32::try_from(index).map_err(|_| self.corrupted())?;
+        // 只有唯一的一块可以为空
+        if len < TAG_LEN || (last && index > 0 && len == TAG_LEN) {
+            return Err(self.corrupted());
+        }
+
+        // 顺序读取时不必重新定位，避免丢弃 BufReader 的缓冲
+        let offset = self.start + index * ENCRYPTED_CHUNK_SIZE as u64;
+        if self.inner_position != Some(offset) {
+            self.inner.seek(SeekFrom::Start(offset))?;
+        }
+        let mut ciphertext = vec![0u8; len];
+        if let Err(e) = self.inner.read_exact(&mut ciphertext) {
+            self.inner_position = None;
+            return Err(if e.kind() == io::ErrorKind::UnexpectedEof { self.corrupted() } else { e });
+        }
+        self.inner_position = Some(offset + len as u64);
+
+        match self.cipher.decrypt(counter, last, &ciphertext) {
+            Some(plaintext) => {
+                self.plaintext = plaintext;
+                self.loaded = Some(index);
+                self.end_verified |= last;
+                Ok(())
+            }
+            None => Err(self.corrupted()),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position >= self.plain_len {
+            // 明文正好在块边界结束时最后一块还没有读过，报告结束前先认证它
+            if !self.end_verified {
+                self.load_chunk(self.chunk_count - 1)?;
+            }
+            return Ok(0);
+        }
+
+        let index = self.position / CHUNK_SIZE as u64;
+        self.load_chunk(index)?;
+
+        let offset = (self.position % CHUNK_SIZE as u64) as usize;
+        let available = &self.plaintext[offset..];
+        let take = available.len().min(buf.len());
+        buf[..take].copy_from_slice(&available[..take]);
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for ChunkReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn encrypt(data: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptWriter::new(Vec::new(), &Key::from([1u8; 32]), [2; NONCE_PREFIX_LEN], b"aad".to_vec());
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(encrypted: &[u8]) -> io::Result<Vec<u8>> {
+        let end = encrypted.len() as u64;
+        let mut reader = ChunkReader::new(Cursor::new(encrypted), &Key::from([1u8; 32]), [2; NONCE_PREFIX_LEN], b"aad".to_vec(), 0, end);
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn round_trip_at_chunk_boundaries() {
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(decrypt(&encrypt(&data)).unwrap(), data, "length {}", len);
+        }
+    }
+
+    // 截掉整块或追加一个空的最后一块都必须被发现
+    #[test]
+    fn rejects_truncated_and_extended_payloads() {
+        let encrypted = encrypt(&vec![7u8; 2 * CHUNK_SIZE]);
+        assert!(decrypt(&encrypted[..ENCRYPTED_CHUNK_SIZE]).is_err());
+        assert!(decrypt(&[]).is_err());
+        assert!(decrypt(&encrypted[..encrypted.len() - 1]).is_err());
+
+        let mut writer = EncryptWriter::new(Vec::new(), &Key::from([1u8; 32]), [2; NONCE_PREFIX_LEN], b"aad".to_vec());
+        writer.write_all(&[7u8; CHUNK_SIZE]).unwrap();
+        writer.write_chunk(false).unwrap();
+        let extended = writer.finish().unwrap();
+        assert!(decrypt(&extended).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+// 单元测试共用的小工具
+
+// 十六进制字符串转字节，测试向量都以这种形式给出
+pub fn hex(text: &str) -> Vec<u8> {
+    assert!(text.len().is_multiple_of(2), "odd hex length");
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+}
+
+pub fn hex32(text: &str) -> [u8; 32] {
+    hex(text).try_into().unwrap()
+}
+
+// 仓库 testdata 目录下的样本文件
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+// 测试用的小目录树（目录、普通文件、空文件、符号链接和一个跨越多个块的大文件），以 tar 数据给出
+pub fn sample_tar() -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, kind: tar::EntryType, data: &[u8], link: Option<&str>| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mode(if kind == tar::EntryType::Directory { 0o755 } else { 0o644 });
+        header.set_mtime(1_600_000_000);
+        header.set_size(data.len() as u64);
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        builder.append_data(&mut header, path, data).unwrap();
+    };
+    append("docs", tar::EntryType::Directory, b"", None);
+    append("docs/readme.txt", tar::EntryType::Regular, b"hello, world\n", None);
+    append("link", tar::EntryType::Symlink, b"", Some("docs/readme.txt"));
+    let big: Vec<u8> = (0..300_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    append("big.bin", tar::EntryType::Regular, &big, None);
+    // sevenz-rust 要求没有内容的文件不夹在有内容的文件之间，空文件放在最后
+    append("empty", tar::EntryType::Regular, b"", None);
+    builder.into_inner().unwrap()
+}
+
+// tar 数据中的各项：路径、类型和内容（符号链接为目标路径）
+pub fn tar_items<R: std::io::Read>(reader: R) -> Vec<(String, tar::EntryType, Vec<u8>)> {
+    let mut archive = tar::Archive::new(reader);
+    let mut items = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().trim_end_matches('/').to_string();
+        let kind = entry.header().entry_type();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+        if let Some(link) = entry.link_name().unwrap() {
+            data = link.to_string_lossy().into_owned().into_bytes();
+        }
+        items.push((path, kind, data));
+    }
+    items
+}
@@ -0,0 +1,389 @@
+// 7z、zip 等其他容器与 tar 数据之间的转换。
+//
+// 读取时把各条目表示为虚拟的 tar 数据：每个条目一个 tar 头（名称过长时先写 GNU 长名称扩展头）
+// + 内容 + 补齐到 512 字节，最后是两个空块，解压、列目录、校验等流程因此不必区分格式。
+// 内容由各格式自己解码，这里只负责定位。
+//
+// 写入时反过来解析打包流程写出的 tar 数据，把合并了长名称和 PAX 扩展头的条目交给各格式。
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use super::index::EntryKind;
+use super::metadata;
+
+const TAR_BLOCK: u64 = 512;
+
+// 解码得到的数据有误（而不是读取文件出错）
+pub(super) fn is_data_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof
+    )
+}
+
+pub(super) fn skip<R: Read>(reader: &mut R, len: u64) -> io::Result<()> {
+    if io::copy(&mut reader.take(len), &mut io::sink())? < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "archive data is truncated"));
+    }
+    Ok(())
+}
+
+// tar 头中的属主取自归档文件本身
+pub(super) fn owner(metadata: &fs::Metadata) -> (u64, u64) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (metadata.uid() as u64, metadata.gid() as u64)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        (0, 0)
+    }
+}
+
+// 归档文件中的一段数据，多个读取器共享同一个文件，每次读取前定位
+pub(super) struct FileRange<R: Read + Seek> {
+    file: Rc<RefCell<R>>,
+    offset: u64,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> FileRange<R> {
+    pub fn new(file: Rc<RefCell<R>>, offset: u64, len: u64) -> Self {
+        FileRange { file, offset, remaining: len }
+    }
+}
+
+impl<R: Read + Seek> Read for FileRange<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        if limit == 0 {
+            return Ok(0);
+        }
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.offset))?;
+        let n = file.read(&mut buf[..limit])?;
+        self.offset += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+// 一个条目的 tar 头。mtime 为 Unix 秒数，owner 为 (uid, gid)
+pub(super) fn header(path: &str, kind: EntryKind, size: u64, mode: u32, mtime: u64, link: Option<&str>, owner: (u64, u64)) -> Vec<u8> {
+    let mut out = Vec::new();
+    if path.len() > 100 {
+        out.extend_from_slice(&long_name(tar::EntryType::GNULongName, path));
+    }
+    if let Some(link) = link
+        && link.len() > 100
+    {
+        out.extend_from_slice(&long_name(tar::EntryType::GNULongLink, link));
+    }
+
+    let mut header = tar::Header::new_gnu();
+    set_field(&mut header.as_old_mut().name, path);
+    header.set_entry_type(match kind {
+        EntryKind::Directory => tar::EntryType::Directory,
+        EntryKind::Symlink => tar::EntryType::Symlink,
+        _ => tar::EntryType::Regular,
+    });
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_uid(owner.0);
+    header.set_gid(owner.1);
+    if let Some(link) = link {
+        set_field(&mut header.as_old_mut().linkname, link);
+    }
+    header.set_cksum();
+    out.extend_from_slice(header.as_bytes());
+    out
+}
+
+// 超长时截断，完整的值在前面的扩展头里
+fn set_field(field: &mut [u8; 100], value: &str) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
+
+fn long_name(entry_type: tar::EntryType, value: &str) -> Vec<u8> {
+    let mut header = tar::Header::new_gnu();
+    set_field(&mut header.as_old_mut().name, "././@LongLink");
+    header.set_entry_type(entry_type);
+    header.set_size(value.len() as u64 + 1);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_cksum();
+    let mut out = header.as_bytes().to_vec();
+    out.extend_from_slice(value.as_bytes());
+    out.push(0);
+    out.resize(out.len().next_multiple_of(TAR_BLOCK as usize), 0);
+    out
+}
+
+// ---- 读取：虚拟 tar 数据的布局 ----
+
+struct Segment {
+    start: u64,
+    header: Vec<u8>,
+    size: u64,
+    end: u64,
+}
+
+// 虚拟 tar 数据中某个位置所在的部分
+pub(super) enum Part<'a> {
+    // tar 头中从该位置起的字节
+    Header(&'a [u8]),
+    // 第 entry 个条目的内容，从 offset 起还有 len 字节
+    Data { entry: usize, offset: u64, len: u64 },
+    // 补齐或结束块，还有若干个 0
+    Zeros(u64),
+}
+
+#[derive(Default)]
+pub(super) struct Layout {
+    segments: Vec<Segment>,
+    end: u64,
+}
+
+impl Layout {
+    // 追加一个条目，返回它在 tar 数据中的偏移
+    pub fn push(&mut self, header: Vec<u8>, size: u64) -> u64 {
+        let start = self.end;
+        self.end = start + header.len() as u64 + size.next_multiple_of(TAR_BLOCK);
+        self.segments.push(Segment { start, header, size, end: self.end });
+        start
+    }
+
+    pub fn plain_len(&self) -> u64 {
+        self.end + 2 * TAR_BLOCK
+    }
+
+    pub fn locate(&self, position: u64) -> Part<'_> {
+        let index = self.segments.partition_point(|segment| segment.start <= position);
+        let Some(segment) = index.checked_sub(1).map(|index| &self.segments[index]).filter(|segment| position < segment.end) else {
+            return Part::Zeros(self.plain_len().saturating_sub(position));
+        };
+        let offset = position - segment.start;
+        let header_len = segment.header.len() as u64;
+        if offset < header_len {
+            Part::Header(&segment.header[offset as usize..])
+        } else if offset < header_len + segment.size {
+            Part::Data { entry: index - 1, offset: offset - header_len, len: header_len + segment.size - offset }
+        } else {
+            Part::Zeros(segment.end - position)
+        }
+    }
+
+    // 按 Seek 的语义计算新位置
+    pub fn seek(&self, position: u64, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.plain_len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => position.checked_add_signed(delta),
+        };
+        target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))
+    }
+}
+
+// ---- 写入：解析 tar 流 ----
+
+// tar 流中的一个条目，已合并 GNU 长名称和 PAX 扩展头
+pub(super) struct TarEntry {
+    pub path: String,
+    pub kind: EntryKind,
+    // 文件内容的长度，其他类型为 0
+    pub size: u64,
+    // 权限位（不含文件类型）
+    pub mode: u32,
+    // Unix 秒数和纳秒
+    pub mtime: (i64, u32),
+    pub link: Option<String>,
+}
+
+// 接收解析出的条目：先是 entry，文件接着是若干次 data 和一次 end
+pub(super) trait TarSink {
+    // 错误信息中的格式名称
+    const FORMAT: &'static str;
+
+    fn entry(&mut self, entry: TarEntry) -> io::Result<()>;
+
+    fn data(&mut self, data: &[u8]) -> io::Result<()>;
+
+    fn end(&mut self) -> io::Result<()>;
+}
+
+// tar 条目内容的去向
+enum Target {
+    File,
+    LongName(Vec<u8>),
+    LongLink(Vec<u8>),
+    Pax(Vec<u8>),
+    Discard,
+}
+
+enum State {
+    Header,
+    Data { remaining: u64, padding: u64, target: Target },
+    Padding(u64),
+    End,
+}
+
+#[derive(Default)]
+struct PaxFields {
+    path: Option<String>,
+    linkpath: Option<String>,
+    mtime: Option<(i64, u32)>,
+}
+
+// 逐块解析 tar 数据。设备文件、FIFO 等跳过，硬链接报错
+pub(super) struct TarParser {
+    block: Vec<u8>,
+    state: State,
+    long_name: Option<String>,
+    long_link: Option<String>,
+    pax: Vec<u8>,
+}
+
+impl TarParser {
+    pub fn new() -> Self {
+        TarParser {
+            block: Vec::with_capacity(TAR_BLOCK as usize),
+            state: State::Header,
+            long_name: None,
+            long_link: None,
+            pax: Vec::new(),
+        }
+    }
+
+    pub fn write<S: TarSink>(&mut self, sink: &mut S, buf: &[u8]) -> io::Result<()> {
+        let mut input = buf;
+        while !input.is_empty() {
+            let state = std::mem::replace(&mut self.state, State::End);
+            self.state = match state {
+                State::Header => {
+                    let take = (TAR_BLOCK as usize - self.block.len()).min(input.len());
+                    self.block.extend_from_slice(&input[..take]);
+                    input = &input[take..];
+                    if self.block.len() == TAR_BLOCK as usize {
+                        let block = std::mem::take(&mut self.block);
+                        self.header(sink, &block)?
+                    } else {
+                        State::Header
+                    }
+                }
+                State::Data { remaining, padding, mut target } => {
+                    let take = remaining.min(input.len() as u64) as usize;
+                    let chunk = &input[..take];
+                    input = &input[take..];
+                    match &mut target {
+                        Target::File => sink.data(chunk)?,
+                        Target::LongName(bytes) | Target::LongLink(bytes) | Target::Pax(bytes) => bytes.extend_from_slice(chunk),
+                        Target::Discard => {}
+                    }
+                    self.data_done(sink, remaining - take as u64, padding, target)?
+                }
+                State::Padding(remaining) => {
+                    let take = remaining.min(input.len() as u64);
+                    input = &input[take as usize..];
+                    if take == remaining { State::Header } else { State::Padding(remaining - take) }
+                }
+                // 结束块之后的数据忽略
+                State::End => {
+                    input = &[];
+                    State::End
+                }
+            };
+        }
+        Ok(())
+    }
+
+    fn header<S: TarSink>(&mut self, sink: &mut S, block: &[u8]) -> io::Result<State> {
+        if block.iter().all(|&byte| byte == 0) {
+            return Ok(State::End);
+        }
+        let header = tar::Header::from_byte_slice(block);
+        let size = header.entry_size()?;
+        let kind = match header.entry_type() {
+            tar::EntryType::GNULongName => return self.data_done(sink, size, padding(size), Target::LongName(Vec::new())),
+            tar::EntryType::GNULongLink => return self.data_done(sink, size, padding(size), Target::LongLink(Vec::new())),
+            tar::EntryType::XHeader => return self.data_done(sink, size, padding(size), Target::Pax(Vec::new())),
+            tar::EntryType::XGlobalHeader => return self.data_done(sink, size, padding(size), Target::Discard),
+            tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Symlink => EntryKind::Symlink,
+            tar::EntryType::Link => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} archives cannot store hard links", S::FORMAT)));
+            }
+            _ => EntryKind::Other,
+        };
+
+        let pax = self.take_pax()?;
+        let path = self.long_name.take()
+            .or(pax.path)
+            .unwrap_or_else(|| String::from_utf8_lossy(&header.path_bytes()).into_owned());
+        let link = self.long_link.take()
+            .or(pax.linkpath)
+            .or_else(|| header.link_name_bytes().map(|link| String::from_utf8_lossy(&link).into_owned()));
+        if kind == EntryKind::Other {
+            return self.data_done(sink, size, padding(size), Target::Discard);
+        }
+
+        sink.entry(TarEntry {
+            path: path.trim_end_matches('/').to_string(),
+            kind,
+            size: if kind == EntryKind::File { size } else { 0 },
+            mode: header.mode().unwrap_or(0o644) & 0o7777,
+            mtime: pax.mtime.unwrap_or((header.mtime().unwrap_or(0) as i64, 0)),
+            link: if kind == EntryKind::Symlink { link } else { None },
+        })?;
+        let target = if kind == EntryKind::File { Target::File } else { Target::Discard };
+        self.data_done(sink, size, padding(size), target)
+    }
+
+    // 条目内容写完后的状态
+    fn data_done<S: TarSink>(&mut self, sink: &mut S, remaining: u64, padding: u64, target: Target) -> io::Result<State> {
+        if remaining > 0 {
+            return Ok(State::Data { remaining, padding, target });
+        }
+        let text = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).trim_end_matches('\0').to_string();
+        match target {
+            Target::File => sink.end()?,
+            Target::LongName(bytes) => self.long_name = Some(text(bytes)),
+            Target::LongLink(bytes) => self.long_link = Some(text(bytes)),
+            Target::Pax(bytes) => self.pax = bytes,
+            Target::Discard => {}
+        }
+        Ok(if padding > 0 { State::Padding(padding) } else { State::Header })
+    }
+
+    fn take_pax(&mut self) -> io::Result<PaxFields> {
+        let data = std::mem::take(&mut self.pax);
+        let mut fields = PaxFields::default();
+        for extension in tar::PaxExtensions::new(&data) {
+            let extension = extension?;
+            let Ok(value) = extension.value() else {
+                continue;
+            };
+            match extension.key() {
+                Ok("path") => fields.path = Some(value.to_string()),
+                Ok("linkpath") => fields.linkpath = Some(value.to_string()),
+                Ok("mtime") => {
+                    fields.mtime = metadata::parse_time(value.as_bytes())
+                        .map(|time| (time.unix_seconds(), time.nanoseconds()));
+                }
+                _ => {}
+            }
+        }
+        Ok(fields)
+    }
+}
+
+fn padding(size: u64) -> u64 {
+    size.next_multiple_of(TAR_BLOCK) - size
+}
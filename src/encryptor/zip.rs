@@ -0,0 +1,1229 @@
+// zip 归档的读写，加密的归档可以用 7-Zip、WinZip 以及 macOS、多数 Linux 发行版自带的解压工具打开。
+//
+// ```text
+// 每个条目：本地文件头（签名 PK 03 04 + 方法、时间、大小、名称、扩展字段）+ 数据
+// 中央目录：每个条目一项，另有属性和本地文件头的位置
+// [zip64 目录结束记录 + 定位器] + 目录结束记录（签名 PK 05 06）
+// ```
+//
+// 加密采用 WinZip AES：方法号为 99，扩展字段 0x9901 记录版本（AE-1、AE-2）、密钥长度和实际的压缩方法。
+// 条目数据为 盐 + 2 字节密码校验值 + AES-CTR 密文 + 10 字节 HMAC-SHA1，
+// 密钥由密码经 PBKDF2-HMAC-SHA1 迭代 1000 次派生，每个条目的盐不同。AE-2 不记录 CRC32，完整性依靠 HMAC。
+//
+// 写入时生成 AE-2、AES-256：文件各自以 Deflate 压缩（已压缩的文件和 Compression::None 时直接存储），
+// 空文件和文件夹没有内容，不加密；符号链接的目标不加密（libarchive 读取文件头时就要取出目标）。
+// 文件名、大小和时间不加密，不需要密码就能列出；不记录所有者和扩展属性，无法表示硬链接。读取时支持存储和 Deflate，不支持传统的 ZipCrypto 加密，条目以虚拟 tar 数据的形式交给解密流程。
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use aes::{Aes128, Aes192, Aes256};
+use ctr::Ctr128LE;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+use super::compress;
+use super::error::EncryptorError;
+use super::index::{EntryKind, IndexEntry};
+use super::kdf::Kdf;
+use super::recipient::Recipient;
+use super::vtar::{self, FileRange, Layout, Part, TarEntry, TarParser, TarSink, is_data_error};
+
+type HmacSha1 = Hmac<Sha1>;
+
+const LOCAL_HEADER_SIGNATURE: [u8; 4] = *b"PK\x03\x04";
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = *b"PK\x01\x02";
+const END_SIGNATURE: [u8; 4] = *b"PK\x05\x06";
+const ZIP64_END_SIGNATURE: [u8; 4] = *b"PK\x06\x06";
+const ZIP64_LOCATOR_SIGNATURE: [u8; 4] = *b"PK\x06\x07";
+const LOCAL_HEADER_LEN: u64 = 30;
+const END_LEN: usize = 22;
+const ZIP64_END_LEN: usize = 56;
+const ZIP64_LOCATOR_LEN: u64 = 20;
+const MAX_COMMENT_LEN: usize = 0xFFFF;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+const METHOD_AES: u16 = 99;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_STRONG_ENCRYPTION: u16 = 1 << 6;
+const FLAG_UTF8: u16 = 1 << 11;
+
+const EXTRA_ZIP64: u16 = 0x0001;
+const EXTRA_TIMESTAMP: u16 = 0x5455;
+const EXTRA_AES: u16 = 0x9901;
+
+// 解压需要的版本：2.0 支持 Deflate 和文件夹，4.5 支持 zip64，5.1 支持 AES
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const VERSION_AES: u16 = 51;
+// 高字节为 3 表示 Unix，外部属性的高 16 位是 st_mode
+const HOST_UNIX: u8 = 3;
+const VERSION_MADE_BY: u16 = ((HOST_UNIX as u16) << 8) | 63;
+
+const AE_1: u16 = 1;
+const AE_2: u16 = 2;
+// 写入时使用强度 3，即 AES-256
+const STRENGTH: u8 = 3;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const ITERATIONS: u32 = 1000;
+pub(super) const KDF: Kdf = Kdf::Pbkdf2Sha1 { iterations: ITERATIONS };
+const VERIFIER_LEN: usize = 2;
+const AUTH_CODE_LEN: usize = 10;
+
+// 超过这个值的大小和偏移记在 zip64 扩展字段里
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+// 写入时内容达到这个长度的文件预留 zip64 字段，压缩后可能略大于原文件
+const ZIP64_THRESHOLD: u64 = 0xF000_0000;
+// 符号链接的内容是目标路径，更长的按普通文件处理
+const MAX_LINK_LEN: u64 = 4096;
+
+const ATTRIBUTE_READONLY: u32 = 0x01;
+const ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const ATTRIBUTE_ARCHIVE: u32 = 0x20;
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_DIRECTORY: u32 = 0o040000;
+const MODE_REGULAR: u32 = 0o100000;
+const MODE_SYMLINK: u32 = 0o120000;
+
+fn method_name(method: u16) -> String {
+    let name = match method {
+        METHOD_STORED => "Stored",
+        METHOD_DEFLATE => "Deflate",
+        9 => "Deflate64",
+        12 => "BZip2",
+        14 => "LZMA",
+        93 => "Zstandard",
+        95 => "XZ",
+        98 => "PPMd",
+        _ => return format!("method {}", method),
+    };
+    name.to_string()
+}
+
+// 文件开头是否为 zip 签名（空归档只有目录结束记录）；读取后回到原位置
+pub(super) fn detect<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let start = reader.stream_position()?;
+    let mut magic = Vec::new();
+    reader.by_ref().take(4).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(magic == LOCAL_HEADER_SIGNATURE || magic == END_SIGNATURE)
+}
+
+// 不需要密码就能知道的归档信息
+pub(super) struct Summary {
+    // 最高的 WinZip AES 版本（AE-1、AE-2），没有 AES 加密的条目时为 0
+    pub version: u8,
+    // 各条目的压缩方法和加密方式
+    pub methods: Vec<String>,
+    pub encrypted: bool,
+}
+
+pub(super) fn inspect<R: Read + Seek>(mut reader: R) -> Result<Summary, EncryptorError> {
+    let records = read_directory(&mut reader)?;
+    let mut methods = Vec::new();
+    let mut version = 0;
+    for record in &records {
+        let mut names = vec![method_name(record.method())];
+        if let Some(aes) = record.aes {
+            names.push(format!("AES-{}", aes.key_len().map_or(0, |len| len * 8)));
+            version = version.max(aes.version);
+        } else if record.flags & FLAG_ENCRYPTED != 0 {
+            names.push("ZipCrypto".to_string());
+        }
+        for name in names {
+            if !methods.contains(&name) {
+                methods.push(name);
+            }
+        }
+    }
+    Ok(Summary { version: version.min(u8::MAX as u16) as u8, methods, encrypted: version > 0 })
+}
+
+// ---- 中央目录 ----
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], EncryptorError> {
+        let end = self.pos.checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(EncryptorError::Corrupted)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, EncryptorError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, EncryptorError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, EncryptorError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, EncryptorError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// 扩展字段 0x9901
+#[derive(Clone, Copy)]
+struct AesInfo {
+    version: u16,
+    strength: u8,
+    method: u16,
+}
+
+impl AesInfo {
+    // 强度 1、2、3 分别为 AES-128、192、256，盐的长度是密钥的一半
+    fn key_len(&self) -> Option<usize> {
+        match self.strength {
+            1 => Some(16),
+            2 => Some(24),
+            3 => Some(32),
+            _ => None,
+        }
+    }
+}
+
+// 中央目录中的一项
+struct Record {
+    name: String,
+    host: u8,
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc: u32,
+    packed: u64,
+    size: u64,
+    external: u32,
+    offset: u64,
+    // 扩展时间戳中的修改时间
+    mtime: Option<u32>,
+    aes: Option<AesInfo>,
+}
+
+impl Record {
+    // AES 加密时方法号为 99，实际的压缩方法在扩展字段里
+    fn method(&self) -> u16 {
+        self.aes.map_or(self.method, |aes| aes.method)
+    }
+}
+
+// 从末尾找到目录结束记录，读出整个中央目录
+fn read_directory<R: Read + Seek>(reader: &mut R) -> Result<Vec<Record>, EncryptorError> {
+    let read_error = EncryptorError::io("Failed to read encrypted file");
+    let file_len = reader.seek(SeekFrom::End(0)).map_err(read_error)?;
+    let tail_len = file_len.min((END_LEN + MAX_COMMENT_LEN) as u64);
+    let tail_start = file_len - tail_len;
+    let mut tail = vec![0u8; tail_len as usize];
+    reader.seek(SeekFrom::Start(tail_start)).map_err(read_error)?;
+    reader.read_exact(&mut tail).map_err(read_error)?;
+    let end_pos = (0..=tail.len().saturating_sub(END_LEN))
+        .rev()
+        .find(|&pos| tail[pos..].starts_with(&END_SIGNATURE) && tail.len() - pos >= END_LEN)
+        .ok_or(EncryptorError::Corrupted)?;
+
+    let mut r = ByteReader::new(&tail[end_pos + 4..]);
+    let (disk, directory_disk) = (r.u16()?, r.u16()?);
+    let (_, count) = (r.u16()?, r.u16()?);
+    let (directory_len, directory_offset) = (r.u32()?, r.u32()?);
+    let (mut count, mut directory_len, mut directory_offset) = (count as u64, directory_len as u64, directory_offset as u64);
+    let end_offset = tail_start + end_pos as u64;
+
+    if count == 0xFFFF || directory_len == ZIP64_LIMIT || directory_offset == ZIP64_LIMIT {
+        let mut locator = [0u8; ZIP64_LOCATOR_LEN as usize];
+        let locator_offset = end_offset.checked_sub(ZIP64_LOCATOR_LEN).ok_or(EncryptorError::Corrupted)?;
+        reader.seek(SeekFrom::Start(locator_offset)).map_err(read_error)?;
+        reader.read_exact(&mut locator).map_err(read_error)?;
+        if locator[..4] == ZIP64_LOCATOR_SIGNATURE {
+            let mut r = ByteReader::new(&locator[8..]);
+            let zip64_offset = r.u64()?;
+            let mut record = [0u8; ZIP64_END_LEN];
+            if zip64_offset.checked_add(ZIP64_END_LEN as u64).is_none_or(|end| end > locator_offset) {
+                return Err(EncryptorError::Corrupted);
+            }
+            reader.seek(SeekFrom::Start(zip64_offset)).map_err(read_error)?;
+            reader.read_exact(&mut record).map_err(read_error)?;
+            if record[..4] != ZIP64_END_SIGNATURE {
+                return Err(EncryptorError::Corrupted);
+            }
+            let mut r = ByteReader::new(&record[32..]);
+            (count, directory_len, directory_offset) = (r.u64()?, r.u64()?, r.u64()?);
+        }
+    } else if disk != 0 || directory_disk != 0 {
+        return Err(EncryptorError::UnsupportedFormat("split zip archives are not supported".to_string()));
+    }
+    if directory_offset.checked_add(directory_len).is_none_or(|end| end > end_offset) {
+        return Err(EncryptorError::Corrupted);
+    }
+
+    let mut directory = vec![0u8; directory_len as usize];
+    reader.seek(SeekFrom::Start(directory_offset)).map_err(read_error)?;
+    reader.read_exact(&mut directory).map_err(read_error)?;
+    // 每项至少 46 字节，避免按恶意数量分配内存
+    let mut records = Vec::with_capacity(count.min(directory_len / 46) as usize);
+    let mut r = ByteReader::new(&directory);
+    for _ in 0..count {
+        records.push(read_record(&mut r)?);
+    }
+    Ok(records)
+}
+
+fn read_record(r: &mut ByteReader) -> Result<Record, EncryptorError> {
+    if r.bytes(4)? != CENTRAL_HEADER_SIGNATURE {
+        return Err(EncryptorError::Corrupted);
+    }
+    let host = (r.u16()? >> 8) as u8;
+    let _version_needed = r.u16()?;
+    let (flags, method, time, date) = (r.u16()?, r.u16()?, r.u16()?, r.u16()?);
+    let (crc, packed, size) = (r.u32()?, r.u32()?, r.u32()?);
+    let (name_len, extra_len, comment_len) = (r.u16()?, r.u16()?, r.u16()?);
+    let (_disk, _internal, external, offset) = (r.u16()?, r.u16()?, r.u32()?, r.u32()?);
+    let name = String::from_utf8_lossy(r.bytes(name_len as usize)?).into_owned();
+    let extra = r.bytes(extra_len as usize)?;
+    r.bytes(comment_len as usize)?;
+
+    let mut record = Record {
+        name,
+        host,
+        flags,
+        method,
+        time,
+        date,
+        crc,
+        packed: packed as u64,
+        size: size as u64,
+        external,
+        offset: offset as u64,
+        mtime: None,
+        aes: None,
+    };
+    let mut fields = ByteReader::new(extra);
+    while fields.data.len() - fields.pos >= 4 {
+        let (id, len) = (fields.u16()?, fields.u16()?);
+        let mut field = ByteReader::new(fields.bytes(len as usize)?);
+        match id {
+            // 只包含头部中为 0xFFFFFFFF 的字段，依次为原始大小、压缩后大小、本地文件头偏移
+            EXTRA_ZIP64 => {
+                for value in [&mut record.size, &mut record.packed, &mut record.offset] {
+                    if *value == ZIP64_LIMIT {
+                        *value = field.u64()?;
+                    }
+                }
+            }
+            EXTRA_TIMESTAMP if !field.is_empty() && field.u8()? & 1 != 0 => record.mtime = Some(field.u32()?),
+            EXTRA_AES => {
+                let version = field.u16()?;
+                let vendor = field.bytes(2)?;
+                let (strength, method) = (field.u8()?, field.u16()?);
+                if vendor == b"AE" {
+                    record.aes = Some(AesInfo { version, strength, method });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(record)
+}
+
+// ---- WinZip AES ----
+
+// CTR 模式，计数器为 16 字节小端序，从 1 开始
+enum Keystream {
+    Aes128(Ctr128LE<Aes128>),
+    Aes192(Ctr128LE<Aes192>),
+    Aes256(Ctr128LE<Aes256>),
+}
+
+impl Keystream {
+    fn new(key: &[u8]) -> Self {
+        let counter = 1u128.to_le_bytes();
+        match key.len() {
+            16 => Keystream::Aes128(Ctr128LE::new_from_slices(key, &counter).expect("key length is checked")),
+            24 => Keystream::Aes192(Ctr128LE::new_from_slices(key, &counter).expect("key length is checked")),
+            _ => Keystream::Aes256(Ctr128LE::new_from_slices(key, &counter).expect("AES-256 keys are 32 bytes")),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            Keystream::Aes128(cipher) => cipher.apply_keystream(data),
+            Keystream::Aes192(cipher) => cipher.apply_keystream(data),
+            Keystream::Aes256(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+// 一个条目的密钥：PBKDF2 输出依次为加密密钥、HMAC 密钥和密码校验值
+struct EntryKeys {
+    keystream: Keystream,
+    // 对密文计算
+    mac: HmacSha1,
+    verifier: [u8; VERIFIER_LEN],
+}
+
+fn derive_keys(password: &str, salt: &[u8], key_len: usize) -> EntryKeys {
+    let mut derived = vec![0u8; 2 * key_len + VERIFIER_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), salt, ITERATIONS, &mut derived);
+    EntryKeys {
+        keystream: Keystream::new(&derived[..key_len]),
+        mac: <HmacSha1 as Mac>::new_from_slice(&derived[key_len..2 * key_len]).expect("HMAC accepts any key length"),
+        verifier: derived[2 * key_len..].try_into().unwrap(),
+    }
+}
+
+// ---- 读取：以虚拟 tar 数据的形式提供 ----
+
+// 一个条目的内容在归档中的位置
+#[derive(Clone, Copy)]
+struct Data {
+    // 本地文件头之后
+    start: u64,
+    packed: u64,
+    size: u64,
+    method: u16,
+    crc: u32,
+    aes: Option<AesInfo>,
+}
+
+impl Data {
+    // AE-2 不记录 CRC32
+    fn crc(&self) -> Option<u32> {
+        match self.aes {
+            Some(aes) if aes.version != AE_1 => None,
+            _ => Some(self.crc),
+        }
+    }
+}
+
+// 解密条目数据，同时对密文计算 HMAC；没有加密时原样读出
+struct Decrypt<R: Read + Seek> {
+    inner: FileRange<R>,
+    keys: Option<EntryKeys>,
+}
+
+impl<R: Read + Seek> Read for Decrypt<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(keys) = &mut self.keys {
+            keys.mac.update(&buf[..n]);
+            keys.keystream.apply(&mut buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+enum Decoder<R: Read + Seek> {
+    Stored(Decrypt<R>),
+    Deflate(DeflateDecoder<Decrypt<R>>),
+}
+
+impl<R: Read + Seek> Decoder<R> {
+    fn into_inner(self) -> Decrypt<R> {
+        match self {
+            Decoder::Stored(reader) => reader,
+            Decoder::Deflate(reader) => reader.into_inner(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Stored(reader) => reader.read(buf),
+            Decoder::Deflate(reader) => reader.read(buf),
+        }
+    }
+}
+
+// 正在解码的条目和已输出的长度，顺序读取时接着解码，向后定位时重新开始
+struct Current<R: Read + Seek> {
+    entry: usize,
+    position: u64,
+    decoder: Decoder<R>,
+}
+
+// 顺序读完一个文件的内容时检查 CRC32
+struct Check {
+    entry: usize,
+    hasher: crc32fast::Hasher,
+    done: u64,
+}
+
+pub(super) struct ZipReader<R: Read + Seek> {
+    file: Rc<RefCell<R>>,
+    password: String,
+    layout: Layout,
+    // 各条目的内容，与 entries 一一对应
+    contents: Vec<Option<Data>>,
+    entries: Vec<IndexEntry>,
+    position: u64,
+    current: Option<Current<R>>,
+    check: Option<Check>,
+    auth_failed: bool,
+}
+
+impl<R: Read + Seek> ZipReader<R> {
+    // 读取中央目录并用第一个加密条目的校验值确认密码；metadata 是归档文件的元数据，tar 头中的属主取自它
+    pub fn open(mut reader: R, password: &str, metadata: &fs::Metadata) -> Result<Self, EncryptorError> {
+        let read_error = EncryptorError::io("Failed to read encrypted file");
+        let records = read_directory(&mut reader)?;
+        for record in &records {
+            if record.flags & FLAG_STRONG_ENCRYPTION != 0 || (record.flags & FLAG_ENCRYPTED != 0 && record.aes.is_none()) {
+                return Err(EncryptorError::UnsupportedFormat(
+                    "only AES-encrypted zip archives are supported, not ZipCrypto".to_string(),
+                ));
+            }
+            if record.aes.is_some_and(|aes| aes.key_len().is_none()) {
+                return Err(EncryptorError::UnsupportedFormat("unknown zip AES key length".to_string()));
+            }
+            if !matches!(record.method(), METHOD_STORED | METHOD_DEFLATE) {
+                return Err(EncryptorError::UnsupportedFormat(format!("zip method {} is not supported", method_name(record.method()))));
+            }
+        }
+
+        let mut contents = Vec::with_capacity(records.len());
+        let mut entries = Vec::with_capacity(records.len());
+        for record in records {
+            let path = record.name.replace('\\', "/").trim_end_matches('/').to_string();
+            if path.is_empty() {
+                return Err(EncryptorError::Corrupted);
+            }
+            let unix_mode = (record.host == HOST_UNIX).then_some(record.external >> 16).filter(|&mode| mode != 0);
+            let directory = record.name.ends_with('/')
+                || unix_mode.map_or(record.external & ATTRIBUTE_DIRECTORY != 0, |mode| mode & MODE_TYPE_MASK == MODE_DIRECTORY);
+            let symlink = unix_mode.is_some_and(|mode| mode & MODE_TYPE_MASK == MODE_SYMLINK) && record.size <= MAX_LINK_LEN;
+            let kind = if directory {
+                EntryKind::Directory
+            } else if symlink {
+                EntryKind::Symlink
+            } else {
+                EntryKind::File
+            };
+            let readonly = record.external & ATTRIBUTE_READONLY != 0;
+            let mode = match unix_mode.map(|mode| mode & 0o7777).filter(|&mode| mode != 0) {
+                Some(mode) => mode,
+                None if kind == EntryKind::Symlink => 0o777,
+                None if kind == EntryKind::Directory => 0o755,
+                None if readonly => 0o444,
+                None => 0o644,
+            };
+            let mtime = record.mtime.map(u64::from).or_else(|| dos_to_unix(record.time, record.date));
+
+            let data = if kind != EntryKind::Directory && record.size > 0 {
+                // 数据在本地文件头之后，本地文件头的扩展字段长度可能与中央目录中的不同
+                let mut header = [0u8; LOCAL_HEADER_LEN as usize];
+                reader.seek(SeekFrom::Start(record.offset)).map_err(read_error)?;
+                reader.read_exact(&mut header).map_err(|e| match e.kind() {
+                    io::ErrorKind::UnexpectedEof => EncryptorError::Corrupted,
+                    _ => read_error(e),
+                })?;
+                if header[..4] != LOCAL_HEADER_SIGNATURE {
+                    return Err(EncryptorError::Corrupted);
+                }
+                let name_len = u16::from_le_bytes([header[26], header[27]]) as u64;
+                let extra_len = u16::from_le_bytes([header[28], header[29]]) as u64;
+                Some(Data {
+                    start: record.offset + LOCAL_HEADER_LEN + name_len + extra_len,
+                    packed: record.packed,
+                    size: record.size,
+                    method: record.method(),
+                    crc: record.crc,
+                    aes: record.aes,
+                })
+            } else {
+                None
+            };
+            entries.push(IndexEntry { path, kind, size: 0, offset: 0, mtime, mode: Some(mode), sha256: None });
+            contents.push(data);
+        }
+
+        let mut archive = ZipReader {
+            file: Rc::new(RefCell::new(reader)),
+            password: password.to_string(),
+            layout: Layout::default(),
+            contents: Vec::new(),
+            entries: Vec::new(),
+            position: 0,
+            current: None,
+            check: None,
+            auth_failed: false,
+        };
+        if let Some(data) = contents.iter().flatten().find(|data| data.aes.is_some()) {
+            match archive.decoder(data) {
+                Ok(_) => {}
+                Err(e) if is_data_error(&e) => return Err(EncryptorError::WrongPassword),
+                Err(e) => return Err(read_error(e)),
+            }
+        }
+
+        let owner = vtar::owner(metadata);
+        let mut layout = Layout::default();
+        for (entry, data) in entries.iter_mut().zip(&mut contents) {
+            // 符号链接的目标写在 tar 头里，打开时读出
+            let link = match (entry.kind, *data) {
+                (EntryKind::Symlink, Some(link)) => {
+                    let target = archive.read_all(&link)
+                        .map_err(|e| if is_data_error(&e) { EncryptorError::Corrupted } else { read_error(e) })?;
+                    Some(String::from_utf8_lossy(&target).into_owned())
+                }
+                (EntryKind::Symlink, None) => Some(String::new()),
+                _ => None,
+            };
+            if entry.kind != EntryKind::File {
+                *data = None;
+            }
+            entry.size = data.map_or(0, |data| data.size);
+            let header = vtar::header(&entry.path, entry.kind, entry.size, entry.mode.unwrap_or(0o644), entry.mtime.unwrap_or(0), link.as_deref(), owner);
+            entry.offset = layout.push(header, entry.size);
+        }
+        archive.layout = layout;
+        archive.contents = contents;
+        archive.entries = entries;
+        Ok(archive)
+    }
+
+    // 条目索引，偏移指向虚拟 tar 数据
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    // 虚拟 tar 数据的总长度
+    pub fn plain_len(&self) -> u64 {
+        self.layout.plain_len()
+    }
+
+    // 解码出错、HMAC 或 CRC 不符
+    pub fn auth_failed(&self) -> bool {
+        self.auth_failed
+    }
+
+    pub fn take_auth_failed(&mut self) -> bool {
+        std::mem::take(&mut self.auth_failed)
+    }
+
+    // 从头解码一个条目；加密的条目先检查密码校验值
+    fn decoder(&self, data: &Data) -> io::Result<Decoder<R>> {
+        let (start, len, keys) = match data.aes {
+            Some(aes) => {
+                let key_len = aes.key_len().expect("key length is checked");
+                let salt_len = key_len / 2;
+                let overhead = (salt_len + VERIFIER_LEN + AUTH_CODE_LEN) as u64;
+                if data.packed < overhead {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "zip entry is too short"));
+                }
+                let mut head = vec![0u8; salt_len + VERIFIER_LEN];
+                FileRange::new(Rc::clone(&self.file), data.start, head.len() as u64).read_exact(&mut head)?;
+                let keys = derive_keys(&self.password, &head[..salt_len], key_len);
+                if keys.verifier != head[salt_len..] {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "zip password check failed"));
+                }
+                (data.start + head.len() as u64, data.packed - overhead, Some(keys))
+            }
+            None => (data.start, data.packed, None),
+        };
+        let reader = Decrypt { inner: FileRange::new(Rc::clone(&self.file), start, len), keys };
+        Ok(match data.method {
+            METHOD_DEFLATE => Decoder::Deflate(DeflateDecoder::new(reader)),
+            _ => Decoder::Stored(reader),
+        })
+    }
+
+    // 内容读完后读完剩余的密文，与末尾的认证码比较
+    fn authenticate(&self, decoder: Decoder<R>, data: &Data) -> io::Result<()> {
+        let mut reader = decoder.into_inner();
+        io::copy(&mut reader, &mut io::sink())?;
+        let Some(keys) = reader.keys else {
+            return Ok(());
+        };
+        let mut code = [0u8; AUTH_CODE_LEN];
+        let offset = data.start + data.packed - AUTH_CODE_LEN as u64;
+        FileRange::new(Rc::clone(&self.file), offset, AUTH_CODE_LEN as u64).read_exact(&mut code)?;
+        keys.mac.verify_truncated_left(&code)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "zip data does not match its authentication code"))
+    }
+
+    fn read_all(&self, data: &Data) -> io::Result<Vec<u8>> {
+        let mut decoder = self.decoder(data)?;
+        let mut content = Vec::new();
+        (&mut decoder).take(data.size).read_to_end(&mut content)?;
+        if (content.len() as u64) < data.size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "zip data is truncated"));
+        }
+        self.authenticate(decoder, data)?;
+        if data.crc().is_some_and(|crc| crc != crc32fast::hash(&content)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "zip data does not match its CRC"));
+        }
+        Ok(content)
+    }
+
+    fn read_data(&mut self, entry: usize, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.contents[entry].expect("entry has data");
+        if !matches!(&self.current, Some(current) if current.entry == entry && current.position <= offset) {
+            let decoder = self.decoder(&data)?;
+            self.current = Some(Current { entry, position: 0, decoder });
+        }
+        let current = self.current.as_mut().expect("decoder was just created");
+        vtar::skip(&mut current.decoder, offset - current.position)?;
+        current.position = offset;
+
+        let n = current.decoder.read(buf)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "zip data is truncated"));
+        }
+        current.position += n as u64;
+        if current.position == data.size {
+            let current = self.current.take().expect("decoder is active");
+            self.authenticate(current.decoder, &data)?;
+        }
+
+        if offset == 0 {
+            self.check = Some(Check { entry, hasher: crc32fast::Hasher::new(), done: 0 });
+        }
+        match &mut self.check {
+            Some(check) if check.entry == entry && check.done == offset => {
+                check.hasher.update(&buf[..n]);
+                check.done += n as u64;
+                if check.done == data.size {
+                    let crc = self.check.take().expect("check is active").hasher.finalize();
+                    if data.crc().is_some_and(|expected| expected != crc) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "zip data does not match its CRC"));
+                    }
+                }
+            }
+            _ => self.check = None,
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Read for ZipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.layout.plain_len() {
+            return Ok(0);
+        }
+        let take = match self.layout.locate(self.position) {
+            Part::Header(available) => {
+                let take = available.len().min(buf.len());
+                buf[..take].copy_from_slice(&available[..take]);
+                take
+            }
+            Part::Data { entry, offset, len } => {
+                let limit = buf.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+                match self.read_data(entry, offset, &mut buf[..limit]) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        if is_data_error(&e) {
+                            self.auth_failed = true;
+                            self.current = None;
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+            Part::Zeros(len) => {
+                let take = buf.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+                buf[..take].fill(0);
+                take
+            }
+        };
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read + Seek> Seek for ZipReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.layout.seek(self.position, pos)?;
+        Ok(self.position)
+    }
+}
+
+// ---- DOS 时间 ----
+
+// 以 UTC 计。DOS 时间只能表示 1980 到 2107 年，超出时取边界；精确的时间另记在扩展时间戳里
+fn dos_time(secs: i64) -> (u16, u16) {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    if year > 2107 {
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+    }
+    let seconds = secs.rem_euclid(86_400);
+    let time = ((seconds / 3600) << 11) | ((seconds % 3600 / 60) << 5) | (seconds % 60 / 2);
+    let date = ((year - 1980) << 9) | ((month as i64) << 5) | day as i64;
+    (time as u16, date as u16)
+}
+
+fn dos_to_unix(time: u16, date: u16) -> Option<u64> {
+    let (year, month, day) = (1980 + (date >> 9) as i64, ((date >> 5) & 0x0F) as u32, (date & 0x1F) as u32);
+    let (hour, minute, second) = ((time >> 11) as u64, ((time >> 5) & 0x3F) as u64, ((time & 0x1F) * 2) as u64);
+    if !(1..=12).contains(&month) || day == 0 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+// 公历日期与 1970-01-01 起的天数互相转换
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// ---- 写入：tar 流中的条目逐个写成 zip 条目 ----
+
+// 写入中央目录需要的信息
+struct Item {
+    name: String,
+    offset: u64,
+    // 加密条目的实际压缩方法；文件夹、空文件和符号链接不加密
+    method: Option<u16>,
+    // 只有不加密的条目记录
+    crc: u32,
+    time: u16,
+    date: u16,
+    mtime: Option<u32>,
+    attributes: u32,
+    packed: u64,
+    size: u64,
+}
+
+impl Item {
+    fn flags(&self) -> u16 {
+        if self.method.is_some() { FLAG_UTF8 | FLAG_ENCRYPTED } else { FLAG_UTF8 }
+    }
+
+    fn version_needed(&self, zip64: bool) -> u16 {
+        match (self.method, zip64) {
+            (Some(_), _) => VERSION_AES,
+            (None, true) => VERSION_ZIP64,
+            (None, false) => VERSION_DEFAULT,
+        }
+    }
+
+    // 扩展时间戳和 AES 字段，本地文件头和中央目录相同
+    fn extra(&self) -> Vec<u8> {
+        let mut extra = Vec::new();
+        if let Some(mtime) = self.mtime {
+            push_extra(&mut extra, EXTRA_TIMESTAMP, &[&[1], &mtime.to_le_bytes()[..]].concat());
+        }
+        if let Some(method) = self.method {
+            let field = [&AE_2.to_le_bytes()[..], b"AE", &[STRENGTH], &method.to_le_bytes()].concat();
+            push_extra(&mut extra, EXTRA_AES, &field);
+        }
+        extra
+    }
+
+    // zip64 时大小记在扩展字段里，文件头中为 0xFFFFFFFF；写完内容后以相同长度重写
+    fn local_header(&self, zip64: bool) -> Vec<u8> {
+        let mut extra = Vec::new();
+        if zip64 {
+            push_extra(&mut extra, EXTRA_ZIP64, &[self.size.to_le_bytes(), self.packed.to_le_bytes()].concat());
+        }
+        extra.extend_from_slice(&self.extra());
+        let (packed, size) = if zip64 { (u32::MAX, u32::MAX) } else { (self.packed as u32, self.size as u32) };
+
+        let mut out = LOCAL_HEADER_SIGNATURE.to_vec();
+        out.extend_from_slice(&self.version_needed(zip64).to_le_bytes());
+        out.extend_from_slice(&self.flags().to_le_bytes());
+        out.extend_from_slice(&self.header_method().to_le_bytes());
+        out.extend_from_slice(&self.time.to_le_bytes());
+        out.extend_from_slice(&self.date.to_le_bytes());
+        // AE-2 的 CRC32 为 0
+        out.extend_from_slice(&self.crc.to_le_bytes());
+        out.extend_from_slice(&packed.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out
+    }
+
+    fn central_header(&self) -> Vec<u8> {
+        let mut zip64 = Vec::new();
+        let mut field = |value: u64| {
+            if value >= ZIP64_LIMIT {
+                zip64.extend_from_slice(&value.to_le_bytes());
+                u32::MAX
+            } else {
+                value as u32
+            }
+        };
+        let (size, packed, offset) = (field(self.size), field(self.packed), field(self.offset));
+        let mut extra = Vec::new();
+        if !zip64.is_empty() {
+            push_extra(&mut extra, EXTRA_ZIP64, &zip64);
+        }
+        extra.extend_from_slice(&self.extra());
+
+        let mut out = CENTRAL_HEADER_SIGNATURE.to_vec();
+        out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        out.extend_from_slice(&self.version_needed(!zip64.is_empty()).to_le_bytes());
+        out.extend_from_slice(&self.flags().to_le_bytes());
+        out.extend_from_slice(&self.header_method().to_le_bytes());
+        out.extend_from_slice(&self.time.to_le_bytes());
+        out.extend_from_slice(&self.date.to_le_bytes());
+        out.extend_from_slice(&self.crc.to_le_bytes());
+        out.extend_from_slice(&packed.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        // 注释长度、磁盘号、内部属性
+        out.extend_from_slice(&[0; 6]);
+        out.extend_from_slice(&self.attributes.to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out
+    }
+
+    fn header_method(&self) -> u16 {
+        if self.method.is_some() { METHOD_AES } else { METHOD_STORED }
+    }
+}
+
+fn push_extra(out: &mut Vec<u8>, id: u16, data: &[u8]) {
+    out.extend_from_slice(&id.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+// 正在写入内容的条目
+struct Entry {
+    item: Item,
+    zip64: bool,
+    // 压缩输出先放在内存中，每次写入后取出加密
+    deflate: Option<DeflateEncoder<Vec<u8>>>,
+    keys: EntryKeys,
+}
+
+impl Entry {
+    fn seal<W: Write>(&mut self, writer: &mut W, mut data: Vec<u8>) -> io::Result<()> {
+        self.keys.keystream.apply(&mut data);
+        self.keys.mac.update(&data);
+        self.item.packed += data.len() as u64;
+        writer.write_all(&data)
+    }
+}
+
+// 接收解析出的 tar 条目，写出本地文件头和加密的内容
+struct Archive<W: Write + Seek> {
+    inner: W,
+    password: String,
+    deflate: bool,
+    items: Vec<Item>,
+    current: Option<Entry>,
+}
+
+// 接收 tar 数据，写出 zip 归档，finish 时写入中央目录
+pub(super) struct Writer<W: Write + Seek> {
+    parser: TarParser,
+    archive: Archive<W>,
+}
+
+// 返回加密用的密码。zip 只能用一个密码加密
+pub(super) fn begin(recipients: &[Recipient]) -> Result<String, EncryptorError> {
+    let [recipient] = recipients else {
+        return Err(EncryptorError::InvalidOptions("a zip archive is protected by a single password".to_string()));
+    };
+    recipient.as_password()
+        .map(str::to_string)
+        .ok_or_else(|| EncryptorError::InvalidOptions("zip archives can only be encrypted with a password".to_string()))
+}
+
+impl<W: Write + Seek> Writer<W> {
+    pub fn new(inner: W, password: String, deflate: bool) -> Self {
+        let archive = Archive { inner, password, deflate, items: Vec::new(), current: None };
+        Writer { parser: TarParser::new(), archive }
+    }
+
+    // 写入中央目录和目录结束记录，条目数、大小或偏移超出范围时先写 zip64 记录
+    pub fn finish(self) -> io::Result<W> {
+        let Archive { mut inner, items, .. } = self.archive;
+        let directory_offset = inner.stream_position()?;
+        for item in &items {
+            inner.write_all(&item.central_header())?;
+        }
+        let directory_end = inner.stream_position()?;
+        let directory_len = directory_end - directory_offset;
+        let count = items.len() as u64;
+
+        if count >= 0xFFFF || directory_len >= ZIP64_LIMIT || directory_offset >= ZIP64_LIMIT {
+            let mut record = ZIP64_END_SIGNATURE.to_vec();
+            record.extend_from_slice(&(ZIP64_END_LEN as u64 - 12).to_le_bytes());
+            record.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+            record.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            record.extend_from_slice(&[0; 8]);
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&count.to_le_bytes());
+            record.extend_from_slice(&directory_len.to_le_bytes());
+            record.extend_from_slice(&directory_offset.to_le_bytes());
+            record.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE);
+            record.extend_from_slice(&0u32.to_le_bytes());
+            record.extend_from_slice(&directory_end.to_le_bytes());
+            record.extend_from_slice(&1u32.to_le_bytes());
+            inner.write_all(&record)?;
+        }
+
+        let count = count.min(0xFFFF) as u16;
+        let mut end = END_SIGNATURE.to_vec();
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(directory_len.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        end.extend_from_slice(&(directory_offset.min(ZIP64_LIMIT) as u32).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        inner.write_all(&end)?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write + Seek> TarSink for Archive<W> {
+    const FORMAT: &'static str = "zip";
+
+    fn entry(&mut self, entry: TarEntry) -> io::Result<()> {
+        let link = entry.link.unwrap_or_default();
+        let (type_bits, attributes) = match entry.kind {
+            EntryKind::Directory => (MODE_DIRECTORY, ATTRIBUTE_DIRECTORY),
+            EntryKind::Symlink => (MODE_SYMLINK, ATTRIBUTE_ARCHIVE),
+            _ => (MODE_REGULAR, ATTRIBUTE_ARCHIVE),
+        };
+        let len = if entry.kind == EntryKind::File { entry.size } else { 0 };
+        let readonly = if entry.mode & 0o222 == 0 { ATTRIBUTE_READONLY } else { 0 };
+        let name = if entry.kind == EntryKind::Directory { format!("{}/", entry.path) } else { entry.path };
+        // 已压缩的文件直接存储
+        let compress = self.deflate && entry.kind == EntryKind::File && !compress::is_precompressed(Path::new(&name));
+        let (time, date) = dos_time(entry.mtime.0);
+        let mut item = Item {
+            name,
+            offset: self.inner.stream_position()?,
+            method: (len > 0).then_some(if compress { METHOD_DEFLATE } else { METHOD_STORED }),
+            crc: 0,
+            time,
+            date,
+            mtime: u32::try_from(entry.mtime.0).ok(),
+            attributes: attributes | readonly | ((type_bits | entry.mode) << 16),
+            packed: 0,
+            size: 0,
+        };
+        if entry.kind == EntryKind::Symlink {
+            item.crc = crc32fast::hash(link.as_bytes());
+            item.packed = link.len() as u64;
+            item.size = link.len() as u64;
+        }
+        let zip64 = len >= ZIP64_THRESHOLD;
+        self.inner.write_all(&item.local_header(zip64))?;
+        if len == 0 {
+            self.inner.write_all(link.as_bytes())?;
+            self.items.push(item);
+            return Ok(());
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let keys = derive_keys(&self.password, &salt, KEY_LEN);
+        self.inner.write_all(&salt)?;
+        self.inner.write_all(&keys.verifier)?;
+        item.packed = (salt.len() + VERIFIER_LEN) as u64;
+        let deflate = compress.then(|| DeflateEncoder::new(Vec::new(), flate2::Compression::default()));
+        self.current = Some(Entry { item, zip64, deflate, keys });
+        Ok(())
+    }
+
+    fn data(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        current.item.size += data.len() as u64;
+        let data = match &mut current.deflate {
+            Some(encoder) => {
+                encoder.write_all(data)?;
+                std::mem::take(encoder.get_mut())
+            }
+            None => data.to_vec(),
+        };
+        current.seal(&mut self.inner, data)
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        let Some(mut current) = self.current.take() else {
+            return Ok(());
+        };
+        if let Some(encoder) = current.deflate.take() {
+            let data = encoder.finish()?;
+            current.seal(&mut self.inner, data)?;
+        }
+        let code = current.keys.mac.finalize().into_bytes();
+        self.inner.write_all(&code[..AUTH_CODE_LEN])?;
+        let mut item = current.item;
+        item.packed += AUTH_CODE_LEN as u64;
+        if !current.zip64 && item.packed >= ZIP64_LIMIT {
+            return Err(io::Error::other("zip entry grew too large while compressing"));
+        }
+
+        // 回填本地文件头中的大小
+        self.inner.seek(SeekFrom::Start(item.offset))?;
+        self.inner.write_all(&item.local_header(current.zip64))?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.items.push(item);
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.parser.write(&mut self.archive, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::encryptor::testutil::{fixture, hex, sample_tar, tar_items};
+
+    fn open(data: Vec<u8>, password: &str) -> Result<ZipReader<Cursor<Vec<u8>>>, EncryptorError> {
+        let metadata = fs::metadata(env!("CARGO_MANIFEST_DIR")).unwrap();
+        ZipReader::open(Cursor::new(data), password, &metadata)
+    }
+
+    fn write(tar: &[u8], password: &str, deflate: bool) -> Vec<u8> {
+        let password = begin(&[Recipient::password(password)]).unwrap();
+        let mut writer = Writer::new(Cursor::new(Vec::new()), password, deflate);
+        writer.write_all(tar).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let tar = sample_tar();
+        for deflate in [true, false] {
+            let data = write(&tar, "secret", deflate);
+            assert_eq!(tar_items(open(data.clone(), "secret").unwrap()), tar_items(tar.as_slice()));
+            assert!(matches!(open(data, "wrong"), Err(EncryptorError::WrongPassword)));
+        }
+    }
+
+    // 改动密文后 HMAC 校验失败，读取报错
+    // WinZip AE-2 的计数器：第 n 块的密钥流为 AES(key, n 的 16 字节小端序)，n 从 1 开始
+    #[test]
+    fn keystream_uses_little_endian_counter() {
+        use aes::cipher::{BlockEncrypt, KeyInit};
+
+        let key = [0x42u8; 32];
+        let mut stream = [0u8; 40];
+        Keystream::new(&key).apply(&mut stream);
+        let cipher = Aes256::new_from_slice(&key).unwrap();
+        for (n, chunk) in stream.chunks(16).enumerate() {
+            let mut block = aes::Block::from((n as u128 + 1).to_le_bytes());
+            cipher.encrypt_block(&mut block);
+            assert_eq!(chunk, &block[..chunk.len()]);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_data() {
+        for deflate in [true, false] {
+            let mut data = write(&sample_tar(), "secret", deflate);
+            let middle = data.len() / 2;
+            data[middle] ^= 1;
+            let mut reader = open(data, "secret").unwrap();
+            let mut plain = Vec::new();
+            assert!(reader.read_to_end(&mut plain).is_err());
+            assert!(reader.take_auth_failed());
+        }
+    }
+
+    // 以下样本由 libarchive 3.8 的 bsdtar（AE-1）和 Info-ZIP 生成，密码都是 "secret"
+
+    #[test]
+    fn reads_aes256_archive_from_libarchive() {
+        let mut archive = tar::Archive::new(open(fixture("zip/libarchive-aes256.zip"), "secret").unwrap());
+        let mut items = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().trim_end_matches('/').to_string();
+            // zip 里没有 PAX 记录，目录的修改时间只在 tar 头中
+            assert_eq!(entry.header().mtime().unwrap(), 1_614_834_367, "{}", path);
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            items.push((path, entry.header().entry_type(), data));
+        }
+        let names: Vec<_> = items.iter().map(|(path, kind, _)| (path.as_str(), *kind)).collect();
+        assert_eq!(
+            names,
+            [("docs", tar::EntryType::Directory), ("docs/hello.txt", tar::EntryType::Regular), ("data.bin", tar::EntryType::Regular)]
+        );
+        assert_eq!(items[1].2, b"hello from libarchive\n");
+        assert_eq!(Sha256::digest(&items[2].2)[..], hex("a6984adb63d192730ecd3cf1ebb90827125395990c8f5c910e26ba3b747226c8"));
+        assert!(matches!(open(fixture("zip/libarchive-aes256.zip"), "wrong"), Err(EncryptorError::WrongPassword)));
+    }
+
+    #[test]
+    fn reads_aes128_archive_from_libarchive() {
+        let items = tar_items(open(fixture("zip/libarchive-aes128.zip"), "secret").unwrap());
+        let hello = items.iter().find(|(path, ..)| path == "docs/hello.txt").unwrap();
+        assert_eq!(hello.2, b"hello from libarchive\n");
+    }
+
+    #[test]
+    fn rejects_zipcrypto_archive() {
+        assert!(matches!(open(fixture("zip/infozip-zipcrypto.zip"), "secret"), Err(EncryptorError::UnsupportedFormat(_))));
+    }
+}
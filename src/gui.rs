@@ -0,0 +1,1271 @@
+use eframe::egui;
+use pw::encryptor;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub fn run() -> Result<(), eframe::Error> {
+    // 测试使用默认选项，不设置窗口大小，避免与不同版本的eframe不兼容，test_comitt2
+    let options = eframe::NativeOptions::default();
+
+    eframe::run_native(
+        "Folder Encryptor",
+        options,
+        Box::new(|cc| {
+            // 配置字体和样式
+            setup_custom_fonts(&cc.egui_ctx);
+            Box::new(MyApp::default())
+        }),
+    )
+}
+
+fn setup_custom_fonts(ctx: &egui::Context) {
+    // 加载默认字体
+    let fonts = egui::FontDefinitions::default();
+
+    // 尝试使用系统内置的中文友好字体
+    #[cfg(target_os = "windows")]
+    {
+        fonts.font_data.insert(
+            "msyh".to_owned(),
+            egui::FontData::from_owned(
+                std::fs::read("C:\\Windows\\Fonts\\msyh.ttc").unwrap_or_else(|_| vec![]), // 读取失败则返回空向量
+            ),
+        );
+        if !fonts
+            .font_data
+            .get("msyh")
+            .map_or(true, |f| f.font.is_empty())
+        {
+            fonts
+                .families
+                .get_mut(&egui::FontFamily::Proportional)
+                .unwrap()
+                .insert(0, "msyh".to_owned());
+        }
+    }
+
+    // 不再尝试加载macOS特定字体，避免找不到字体的错误
+
+    // 设置较大的默认字体大小
+    let mut style = (*ctx.style()).clone();
+    style.text_styles = [
+        (
+            egui::TextStyle::Heading,
+            egui::FontId::new(24.0, egui::FontFamily::Proportional),
+        ),
+        (
+            egui::TextStyle::Body,
+            egui::FontId::new(18.0, egui::FontFamily::Proportional),
+        ),
+        (
+            egui::TextStyle::Button,
+            egui::FontId::new(18.0, egui::FontFamily::Proportional),
+        ),
+        (
+            egui::TextStyle::Small,
+            egui::FontId::new(14.0, egui::FontFamily::Proportional),
+        ),
+    ]
+    .into();
+
+    ctx.set_fonts(fonts);
+    ctx.set_style(style);
+}
+
+// 解密、校验时可以选择的归档：.aes（包括旧版 openssl 生成的文件）、age、7z 和 zip
+const ARCHIVE_EXTENSIONS: &[&str] = &["aes", "age", "7z", "zip"];
+
+enum OperationResult {
+    Encrypted(encryptor::EncryptReport),
+    Decrypted(encryptor::DecryptReport),
+    Listed(Vec<encryptor::ArchiveEntry>),
+    Verified(encryptor::VerifyReport),
+    // 添加接收者后归档中的接收者总数
+    RecipientsAdded(usize),
+    Upgraded(encryptor::EncryptReport),
+    Error(encryptor::EncryptorError),
+    None,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Encrypt,
+    Decrypt,
+    // 只校验归档，不写任何文件
+    Verify,
+}
+
+enum StatusMessage {
+    Info(String),
+    Error(String),
+}
+
+struct MyApp {
+    // 加密时可以是多个文件和文件夹，解密时只有一个加密文件
+    selected_paths: Vec<PathBuf>,
+    password: String,
+    encrypting: bool,
+    decrypting: bool,
+    status_message: Option<StatusMessage>,
+    operation_in_progress: bool,
+    operation_result: Arc<Mutex<OperationResult>>,
+    mode: Mode,
+    show_password: bool,
+    // 加密输出的容器格式
+    format: encryptor::ArchiveFormat,
+    kdf: encryptor::Kdf,
+    compression: encryptor::Compression,
+    // 加密时不记录、解密时不还原文件属主
+    skip_ownership: bool,
+    // 加密时额外的公钥接收者，每行一个
+    recipients: String,
+    // 解密时尝试的私钥文件
+    identity_files: Vec<PathBuf>,
+    // 工作线程上报的最新进度
+    progress: Arc<Mutex<Option<encryptor::Progress>>>,
+    // 正在运行的任务的取消令牌
+    cancel: Option<encryptor::CancelToken>,
+    // 输出已存在时等待用户选择处理方式
+    conflict: Option<PathBuf>,
+    // 用户选择的输出位置，None 表示保存在源文件旁边
+    destination: Option<PathBuf>,
+    // 解密模式下列出的归档内容
+    listing: Option<Listing>,
+    // 最近一次校验的逐条结果
+    verify_report: Option<encryptor::VerifyReport>,
+    // 本次解密只解压这些归档路径，为空时解压全部；冲突后重新执行时沿用
+    extract_only: Vec<String>,
+    // 选中的是旧版本用 openssl 生成的文件，提示转换为新格式
+    legacy: bool,
+}
+
+// 归档内容和用户勾选的条目
+struct Listing {
+    entries: Vec<encryptor::ArchiveEntry>,
+    checked: BTreeSet<String>,
+}
+
+impl Default for MyApp {
+    fn default() -> Self {
+        Self {
+            selected_paths: Vec::new(),
+            password: String::new(),
+            encrypting: false,
+            decrypting: false,
+            status_message: None,
+            operation_in_progress: false,
+            operation_result: Arc::new(Mutex::new(OperationResult::None)),
+            mode: Mode::Encrypt,
+            show_password: false,
+            format: encryptor::ArchiveFormat::default(),
+            kdf: encryptor::Kdf::default(),
+            compression: encryptor::Compression::default(),
+            skip_ownership: false,
+            recipients: String::new(),
+            identity_files: Vec::new(),
+            progress: Arc::new(Mutex::new(None)),
+            cancel: None,
+            conflict: None,
+            destination: None,
+            listing: None,
+            verify_report: None,
+            extract_only: Vec::new(),
+            legacy: false,
+        }
+    }
+}
+
+impl MyApp {
+    // 创建进度回调：保存最新进度并请求重绘
+    fn progress_callback(&self, ctx: &egui::Context) -> encryptor::ProgressCallback {
+        *self.progress.lock().unwrap() = None;
+        let progress = self.progress.clone();
+        let ctx = ctx.clone();
+        Arc::new(move |update: &encryptor::Progress| {
+            *progress.lock().unwrap() = Some(update.clone());
+            ctx.request_repaint();
+        })
+    }
+
+    // 解析接收者输入框，忽略空行和 "#" 开头的注释
+    fn parse_recipients(&self) -> Result<Vec<encryptor::Recipient>, encryptor::EncryptorError> {
+        self.recipients
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(encryptor::Recipient::parse)
+            .collect()
+    }
+
+    fn load_identities(&self) -> Result<Vec<encryptor::Identity>, encryptor::EncryptorError> {
+        let mut identities = Vec::new();
+        for path in &self.identity_files {
+            identities.extend(encryptor::Identity::read_file(path)?);
+        }
+        Ok(identities)
+    }
+
+    // 启动任务前的检查失败
+    fn fail(&mut self, error: encryptor::EncryptorError) {
+        self.operation_in_progress = false;
+        self.status_message = Some(StatusMessage::Error(format!("Error: {}", error)));
+    }
+
+    fn start_encrypt(&mut self, ctx: &egui::Context, conflict: encryptor::ConflictPolicy) {
+        self.operation_in_progress = true;
+        self.encrypting = true;
+        self.decrypting = false;
+        self.status_message = Some(StatusMessage::Info("Encrypting...".to_string()));
+
+        if !self.selected_paths.is_empty() {
+            let inputs = self.selected_paths.clone();
+            // 检查是否都存在
+            if let Some(missing) = inputs.iter().find(|input| !input.exists()) {
+                self.operation_in_progress = false;
+                self.status_message = Some(StatusMessage::Error(format!("Error: '{}' does not exist", missing.display())));
+                return;
+            }
+
+            // 7z、zip 不显示接收者设置，忽略之前填写的公钥
+            let recipients = match self.parse_recipients() {
+                _ if self.password_only() => Vec::new(),
+                Ok(recipients) => recipients,
+                Err(error) => return self.fail(error),
+            };
+            let password = self.password.clone();
+            let options = encryptor::EncryptOptions {
+                format: self.format,
+                kdf: self.kdf,
+                compression: self.compression,
+                destination: self.destination.clone(),
+                progress: Some(self.progress_callback(ctx)),
+                cancel: Some(self.cancel_token()),
+                conflict,
+                skip_ownership: self.skip_ownership,
+                recipients,
+            };
+            let result_arc = self.operation_result.clone();
+            let ctx = ctx.clone();
+
+            // 在新线程中执行加密操作，以避免阻塞UI
+            thread::spawn(move || {
+                let result = encryptor::encrypt_paths(&inputs, &password, &options);
+
+                // 存储结果
+                let operation_result = match result {
+                    Ok(report) => OperationResult::Encrypted(report),
+                    Err(err) => OperationResult::Error(err),
+                };
+
+                // 更新共享状态
+                {
+                    let mut locked_result = result_arc.lock().unwrap();
+                    *locked_result = operation_result;
+                }
+
+                // 通知UI需要更新
+                ctx.request_repaint();
+            });
+        }
+    }
+
+    fn start_decrypt(&mut self, ctx: &egui::Context, conflict: encryptor::ConflictPolicy) {
+        self.operation_in_progress = true;
+        self.encrypting = false;
+        self.decrypting = true;
+        self.status_message = Some(StatusMessage::Info("Decrypting file...".to_string()));
+
+        if let Some(file_path) = self.selected_paths.first().cloned() {
+            // 检查是否为文件
+            if !file_path.is_file() {
+                self.operation_in_progress = false;
+                self.status_message = Some(StatusMessage::Error(format!("Error: '{}' is not a file", file_path.display())));
+                return;
+            }
+
+            let identities = match self.load_identities() {
+                Ok(identities) => identities,
+                Err(error) => return self.fail(error),
+            };
+            let password = self.password.clone();
+            let options = encryptor::DecryptOptions {
+                destination: self.destination.clone(),
+                progress: Some(self.progress_callback(ctx)),
+                cancel: Some(self.cancel_token()),
+                conflict,
+                select: self.extract_only.clone(),
+                skip_ownership: self.skip_ownership,
+                identities,
+            };
+            let result_arc = self.operation_result.clone();
+            let ctx = ctx.clone();
+
+            // 在新线程中执行解密操作，以避免阻塞UI
+            thread::spawn(move || {
+                let result = encryptor::decrypt_folder(&file_path, &password, &options);
+
+                // 存储结果
+                let operation_result = match result {
+                    Ok(report) => OperationResult::Decrypted(report),
+                    Err(err) => OperationResult::Error(err),
+                };
+
+                // 更新共享状态
+                {
+                    let mut locked_result = result_arc.lock().unwrap();
+                    *locked_result = operation_result;
+                }
+
+                // 通知UI需要更新
+                ctx.request_repaint();
+            });
+        }
+    }
+
+    // 校验密码、认证标签和文件哈希，不写任何文件
+    fn start_verify(&mut self, ctx: &egui::Context) {
+        let Some(file_path) = self.selected_paths.first().cloned() else {
+            return;
+        };
+        self.operation_in_progress = true;
+        self.verify_report = None;
+        self.status_message = Some(StatusMessage::Info("Verifying...".to_string()));
+
+        let identities = match self.load_identities() {
+            Ok(identities) => identities,
+            Err(error) => return self.fail(error),
+        };
+        let password = self.password.clone();
+        let options = encryptor::VerifyOptions {
+            progress: Some(self.progress_callback(ctx)),
+            cancel: Some(self.cancel_token()),
+            identities,
+        };
+        let result_arc = self.operation_result.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let operation_result = match encryptor::verify_archive(&file_path, &password, &options) {
+                Ok(report) => OperationResult::Verified(report),
+                Err(err) => OperationResult::Error(err),
+            };
+            *result_arc.lock().unwrap() = operation_result;
+            ctx.request_repaint();
+        });
+    }
+
+    // 只解密索引或 tar 头来列出内容，不写任何文件
+    fn start_list(&mut self, ctx: &egui::Context) {
+        let Some(file_path) = self.selected_paths.first().cloned() else {
+            return;
+        };
+        self.operation_in_progress = true;
+        self.listing = None;
+        self.status_message = Some(StatusMessage::Info("Reading archive contents...".to_string()));
+        *self.progress.lock().unwrap() = None;
+
+        let identities = match self.load_identities() {
+            Ok(identities) => identities,
+            Err(error) => return self.fail(error),
+        };
+        let password = self.password.clone();
+        let result_arc = self.operation_result.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let operation_result = match encryptor::list_archive(&file_path, &password, &identities) {
+                Ok(entries) => OperationResult::Listed(entries),
+                Err(err) => OperationResult::Error(err),
+            };
+            *result_arc.lock().unwrap() = operation_result;
+            ctx.request_repaint();
+        });
+    }
+
+    // 把接收者输入框中的公钥加入选中的归档，只重写文件头，不重新加密内容
+    fn start_add_recipients(&mut self, ctx: &egui::Context) {
+        let Some(file_path) = self.selected_paths.first().cloned() else {
+            return;
+        };
+        self.operation_in_progress = true;
+        self.status_message = Some(StatusMessage::Info("Adding recipients...".to_string()));
+        *self.progress.lock().unwrap() = None;
+
+        let recipients = match self.parse_recipients() {
+            Ok(recipients) => recipients,
+            Err(error) => return self.fail(error),
+        };
+        if recipients.is_empty() {
+            return self.fail(encryptor::EncryptorError::InvalidOptions("enter at least one public key to add".to_string()));
+        }
+        let identities = match self.load_identities() {
+            Ok(identities) => identities,
+            Err(error) => return self.fail(error),
+        };
+        let password = self.password.clone();
+        let options = encryptor::AddRecipientsOptions {
+            identities,
+            recipients,
+            kdf: self.kdf,
+        };
+        let result_arc = self.operation_result.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let operation_result = match encryptor::add_recipients(&file_path, &password, &options) {
+                Ok(count) => OperationResult::RecipientsAdded(count),
+                Err(err) => OperationResult::Error(err),
+            };
+            *result_arc.lock().unwrap() = operation_result;
+            ctx.request_repaint();
+        });
+    }
+
+    // 把旧格式的文件重新加密为当前格式并替换原文件，沿用密码，接收者输入框中的公钥一并加入
+    fn start_upgrade(&mut self, ctx: &egui::Context) {
+        let Some(file_path) = self.selected_paths.first().cloned() else {
+            return;
+        };
+        self.operation_in_progress = true;
+        self.status_message = Some(StatusMessage::Info("Converting to the new format...".to_string()));
+
+        let recipients = match self.parse_recipients() {
+            Ok(recipients) => recipients,
+            Err(error) => return self.fail(error),
+        };
+        let identities = match self.load_identities() {
+            Ok(identities) => identities,
+            Err(error) => return self.fail(error),
+        };
+        let password = self.password.clone();
+        let options = encryptor::UpgradeOptions {
+            identities,
+            encrypt: encryptor::EncryptOptions {
+                kdf: self.kdf,
+                compression: self.compression,
+                recipients,
+                progress: Some(self.progress_callback(ctx)),
+                cancel: Some(self.cancel_token()),
+                ..Default::default()
+            },
+        };
+        let result_arc = self.operation_result.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let operation_result = match encryptor::upgrade_archive(&file_path, &password, &options) {
+                Ok(report) => OperationResult::Upgraded(report),
+                Err(err) => OperationResult::Error(err),
+            };
+            *result_arc.lock().unwrap() = operation_result;
+            ctx.request_repaint();
+        });
+    }
+
+    // 公钥接收者：除密码外，对应私钥的持有者也能解密
+    fn recipients_ui(&mut self, ui: &mut egui::Ui) {
+        let count = self.recipients.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).count();
+        egui::CollapsingHeader::new(format!("Recipients ({})", count)).show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut self.recipients)
+                    .hint_text("age1... or ssh-ed25519 AAAA..., one per line")
+                    .desired_rows(3),
+            );
+            if ui.button("Add from File...").clicked()
+                && let Some(path) = self.file_dialog().pick_file()
+            {
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => {
+                        if !self.recipients.is_empty() && !self.recipients.ends_with('\n') {
+                            self.recipients.push('\n');
+                        }
+                        self.recipients.push_str(&text);
+                    }
+                    Err(error) => {
+                        self.status_message = Some(StatusMessage::Error(format!("Error: {}: {}", path.display(), error)));
+                    }
+                }
+            }
+        });
+    }
+
+    // 解密时与密码一起尝试的私钥文件：age 身份文件或未加密的 ssh-ed25519 私钥
+    fn identities_ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Identity files ({})", self.identity_files.len())).show(ui, |ui| {
+            for path in &self.identity_files {
+                ui.label(path.display().to_string());
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add Identity File...").clicked()
+                    && let Some(path) = self.file_dialog().pick_file()
+                    && !self.identity_files.contains(&path)
+                {
+                    self.identity_files.push(path);
+                }
+                if !self.identity_files.is_empty() && ui.button("Clear").clicked() {
+                    self.identity_files.clear();
+                }
+            });
+        });
+    }
+
+    fn file_dialog(&self) -> rfd::FileDialog {
+        // 短暂延迟，确保文件系统状态已更新
+        // 注意：在生产环境中应考虑更稳健的方式
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        rfd::FileDialog::new().set_directory(std::env::current_dir().unwrap_or_default()) // 重新设置为当前目录，强制刷新
+    }
+
+    // 更新选中的路径，之前选择的输出位置不再适用
+    fn set_selection(&mut self, paths: Vec<PathBuf>) {
+        self.legacy = match paths.as_slice() {
+            [path] if self.mode != Mode::Encrypt => {
+                encryptor::archive_info(path).is_ok_and(|info| info.format == encryptor::ArchiveFormat::OpenSsl)
+            }
+            _ => false,
+        };
+        self.selected_paths = paths;
+        self.destination = None;
+        self.listing = None;
+        self.verify_report = None;
+        self.status_message = None;
+    }
+
+    // 输出位置：加密时选择保存的文件，解密时选择解压到的文件夹
+    fn destination_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Save to…").clicked() {
+                let source = self.selected_paths.first().map(PathBuf::as_path);
+                let directory = source
+                    .and_then(Path::parent)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+                let picked = if self.mode == Mode::Encrypt {
+                    // 与引擎的默认名称一致：单个输入为 <name>.<扩展名>，多个为 archive.<扩展名>
+                    let extension = self.format.extension();
+                    let name = match self.selected_paths.as_slice() {
+                        [single] => single
+                            .file_name()
+                            .map(|name| format!("{}.{}", name.to_string_lossy(), extension))
+                            .unwrap_or_default(),
+                        _ => format!("archive.{}", extension),
+                    };
+                    // 过滤器只按最后一段扩展名匹配，.tar.age 取 age
+                    let filter = extension.rsplit('.').next().unwrap_or(extension);
+                    rfd::FileDialog::new()
+                        .set_directory(directory)
+                        .set_file_name(name)
+                        .add_filter("Encrypted files", &[filter])
+                        .save_file()
+                } else {
+                    rfd::FileDialog::new()
+                        .set_directory(directory)
+                        .set_title("Select output folder")
+                        .pick_folder()
+                };
+                if picked.is_some() {
+                    self.destination = picked;
+                }
+            }
+
+            match &self.destination {
+                Some(destination) => {
+                    ui.label(destination.display().to_string());
+                    if ui.button("Default").clicked() {
+                        self.destination = None;
+                    }
+                }
+                None => {
+                    ui.label(if self.mode == Mode::Encrypt { "Next to the selection" } else { "Next to the file" });
+                }
+            }
+        });
+    }
+
+    // 输出已存在时的对话框，选择处理方式后重新执行同一个任务
+    fn conflict_dialog(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.conflict.clone() else {
+            return;
+        };
+        // 切换了模式或清除了选择时，之前的任务已不再适用
+        if self.selected_paths.is_empty() {
+            self.conflict = None;
+            return;
+        }
+        let mut choice = None;
+        egui::Window::new("Output already exists")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("'{}' already exists. What should be done?", path.display()));
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Keep both").on_hover_text("Write to a new name such as \"name (1)\"").clicked() {
+                        choice = Some(Some(encryptor::ConflictPolicy::Rename));
+                    }
+                    if ui.button("Overwrite").on_hover_text("Replace the existing item").clicked() {
+                        choice = Some(Some(encryptor::ConflictPolicy::Overwrite));
+                    }
+                    // 合并只对解压出的文件夹有意义
+                    if self.mode == Mode::Decrypt
+                        && ui.button("Merge").on_hover_text("Extract into the existing folder").clicked()
+                    {
+                        choice = Some(Some(encryptor::ConflictPolicy::Merge));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(None);
+                    }
+                });
+            });
+
+        match choice {
+            Some(Some(policy)) => {
+                self.conflict = None;
+                if self.mode == Mode::Encrypt {
+                    self.start_encrypt(ctx, policy);
+                } else {
+                    self.start_decrypt(ctx, policy);
+                }
+            }
+            Some(None) => self.conflict = None,
+            None => {}
+        }
+    }
+
+    // 为新任务创建取消令牌
+    fn cancel_token(&mut self) -> encryptor::CancelToken {
+        let token = encryptor::CancelToken::new();
+        self.cancel = Some(token.clone());
+        token
+    }
+
+    fn cancel_ui(&self, ui: &mut egui::Ui) {
+        if let Some(cancel) = &self.cancel {
+            let cancelling = cancel.is_cancelled();
+            if ui.add_enabled(!cancelling, egui::Button::new(if cancelling { "Cancelling..." } else { "Cancel" })).clicked() {
+                cancel.cancel();
+            }
+        }
+    }
+
+    // 进度条、速度和剩余时间；还没有进度时只显示转圈
+    fn progress_ui(&self, ui: &mut egui::Ui) {
+        let progress = self.progress.lock().unwrap().clone();
+        let Some(progress) = progress else {
+            ui.horizontal_centered(|ui| {
+                ui.spinner();
+                ui.label("Operation in progress...");
+            });
+            return;
+        };
+
+        match progress.phase {
+            encryptor::Phase::Scan => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!(
+                        "Scanning: {} entries, {}",
+                        progress.scanned,
+                        format_bytes(progress.total_bytes.unwrap_or(0))
+                    ));
+                });
+            }
+            phase => {
+                let bytes = match progress.total_bytes {
+                    Some(total) => format!("{} / {}", format_bytes(progress.bytes), format_bytes(total)),
+                    None => format_bytes(progress.bytes),
+                };
+                let mut bar = egui::ProgressBar::new(progress.fraction().unwrap_or(0.0))
+                    .text(format!("{}: {}", phase.label(), bytes));
+                if progress.fraction().is_none() {
+                    bar = bar.animate(true);
+                }
+                ui.add(bar);
+
+                let entries = match progress.total_entries {
+                    Some(total) => format!("{} / {} entries", progress.entries, total),
+                    None => format!("{} entries", progress.entries),
+                };
+                let speed = progress.throughput()
+                    .map(|rate| format!(", {}/s", format_bytes(rate as u64)))
+                    .unwrap_or_default();
+                let eta = progress.eta()
+                    .map(|eta| format!(", {} left", format_duration(eta)))
+                    .unwrap_or_default();
+                ui.label(format!("{}{}{}", entries, speed, eta));
+                if !progress.current.is_empty() {
+                    ui.small(&progress.current);
+                }
+            }
+        }
+    }
+
+    // 归档内容的树形视图，文件夹可以展开和折叠，勾选的条目可以单独解压
+    fn listing_ui(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let Some(listing) = &mut self.listing else {
+            return;
+        };
+        let files = listing.entries.iter().filter(|entry| entry.kind == encryptor::EntryKind::File);
+        let total: u64 = files.clone().map(|entry| entry.size).sum();
+        ui.label(format!("{} entries, {} files, {}", listing.entries.len(), files.count(), format_bytes(total)));
+
+        let tree = TreeNode::build(&listing.entries);
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            for (name, node) in &tree.children {
+                node.ui(ui, name, name, false, &mut listing.checked);
+            }
+        });
+
+        let checked = listing.checked.len();
+        let enabled = checked > 0 && !self.operation_in_progress && self.conflict.is_none();
+        let mut extract = false;
+        ui.horizontal(|ui| {
+            extract = ui.add_enabled(enabled, egui::Button::new(format!("Extract Selected ({})", checked))).clicked();
+            if ui.add_enabled(checked > 0, egui::Button::new("Clear Selection")).clicked() {
+                listing.checked.clear();
+            }
+        });
+        if extract {
+            self.extract_only = listing.checked.iter().cloned().collect();
+            self.start_decrypt(ctx, encryptor::ConflictPolicy::Fail);
+        }
+    }
+
+    // 逐个条目显示校验结果，失败的条目排在前面
+    fn verify_report_ui(&self, ui: &mut egui::Ui) {
+        let Some(report) = &self.verify_report else {
+            return;
+        };
+        let (failed, passed): (Vec<_>, Vec<_>) = report.results.iter().partition(|result| !result.status.is_ok());
+        let results: Vec<_> = failed.into_iter().chain(passed).collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical().max_height(300.0).show_rows(ui, row_height, results.len(), |ui, rows| {
+            for result in &results[rows] {
+                ui.horizontal(|ui| {
+                    if result.status.is_ok() {
+                        ui.label("✔");
+                    } else {
+                        ui.colored_label(ui.style().visuals.error_fg_color, "✘");
+                    }
+                    ui.label(&result.path);
+                    ui.weak(result.status.to_string());
+                });
+            }
+        });
+    }
+
+    // 容器格式：age 文件可以在没有本工具的机器上用 age 或 rage 解密
+    fn format_settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Format:");
+            ui.radio_value(&mut self.format, encryptor::ArchiveFormat::Native, ".aes")
+                .on_hover_text("Compressed, with an index for fast listing; recipients can be added later");
+            ui.radio_value(&mut self.format, encryptor::ArchiveFormat::Age, "age")
+                .on_hover_text("Opens with the age or rage tools; uncompressed, and a password cannot be combined with public keys");
+            ui.radio_value(&mut self.format, encryptor::ArchiveFormat::SevenZip, "7z")
+                .on_hover_text("Opens with 7-Zip; protected by the password only, file names included");
+            ui.radio_value(&mut self.format, encryptor::ArchiveFormat::Zip, "zip")
+                .on_hover_text("Opens with 7-Zip and most built-in unzip tools (AES-256); protected by the password only, file names are visible");
+        });
+    }
+
+    // 7z、zip 只能用一个密码加密
+    fn password_only(&self) -> bool {
+        matches!(self.format, encryptor::ArchiveFormat::SevenZip | encryptor::ArchiveFormat::Zip)
+    }
+
+    // 压缩设置；jpg、mp4、zip 等已压缩的文件总是原样存储
+    fn compression_settings_ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Compression ({})", self.compression)).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let zstd = matches!(self.compression, encryptor::Compression::Zstd { .. });
+                if ui.radio(zstd, "zstd").on_hover_text("Good ratio at reasonable speed").clicked() && !zstd {
+                    self.compression = encryptor::Compression::default();
+                }
+                ui.radio_value(&mut self.compression, encryptor::Compression::Lz4, "lz4")
+                    .on_hover_text("Fastest, lower ratio");
+                ui.radio_value(&mut self.compression, encryptor::Compression::None, "None");
+            });
+            if let encryptor::Compression::Zstd { level } = &mut self.compression {
+                ui.horizontal(|ui| {
+                    ui.label("Level:");
+                    ui.add(egui::Slider::new(level, encryptor::ZSTD_LEVELS));
+                });
+            }
+        });
+    }
+
+    // Argon2id 参数设置，数值越大越能抵抗暴力破解，但解锁也越慢
+    fn kdf_settings_ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Key derivation (Argon2id)").show(ui, |ui| {
+            if let encryptor::Kdf::Argon2id { memory_kib, iterations, parallelism } = &mut self.kdf {
+                let mut memory_mib = *memory_kib / 1024;
+                ui.horizontal(|ui| {
+                    ui.label("Memory (MiB):");
+                    ui.add(egui::DragValue::new(&mut memory_mib).clamp_range(8..=4096));
+                    ui.label("Iterations:");
+                    ui.add(egui::DragValue::new(iterations).clamp_range(1..=64));
+                    ui.label("Parallelism:");
+                    ui.add(egui::DragValue::new(parallelism).clamp_range(1..=16));
+                });
+                *memory_kib = memory_mib * 1024;
+            }
+
+            ui.horizontal(|ui| {
+                // 校准会实际运行几次 Argon2id，界面会短暂停顿
+                if ui.button("Calibrate for 1 s unlock").clicked() {
+                    self.kdf = encryptor::calibrate_kdf(Duration::from_secs(1));
+                }
+                if ui.button("Reset").clicked() {
+                    self.kdf = encryptor::Kdf::default();
+                }
+            });
+        });
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // 检查上次操作的结果
+        let mut result = OperationResult::None;
+        {
+            let mut locked_result = self.operation_result.lock().unwrap();
+            std::mem::swap(&mut result, &mut *locked_result);
+        }
+
+        // 如果有结果，更新状态
+        if !matches!(result, OperationResult::None) {
+            self.cancel = None;
+        }
+        match result {
+            OperationResult::Encrypted(report) => {
+                self.operation_in_progress = false;
+                let mut message = format!(
+                    "Encrypted to: {} ({} entries, {} bytes) (Please select folder/file again to refresh view)",
+                    report.output.display(),
+                    report.entries,
+                    report.bytes
+                );
+                // 套接字无法打包，列出被跳过的路径
+                if !report.skipped.is_empty() {
+                    message.push_str(&format!("\n{} sockets were skipped:\n  {}", report.skipped.len(), report.skipped.join("\n  ")));
+                }
+                self.status_message = Some(StatusMessage::Info(message));
+                self.selected_paths.clear(); // 清除选中路径，强制用户重新选择
+            }
+            OperationResult::Verified(report) => {
+                self.operation_in_progress = false;
+                let failed = report.failures().count();
+                self.status_message = Some(if report.passed() {
+                    StatusMessage::Info(format!(
+                        "Verification passed: {} entries, {}",
+                        report.entries,
+                        format_bytes(report.bytes)
+                    ))
+                } else {
+                    let incomplete = if report.complete { "" } else { "; the end of the archive is damaged or missing" };
+                    StatusMessage::Error(format!(
+                        "Verification FAILED: {} of {} entries{}",
+                        failed,
+                        report.results.len(),
+                        incomplete
+                    ))
+                });
+                self.verify_report = Some(report);
+            }
+            OperationResult::RecipientsAdded(count) => {
+                self.operation_in_progress = false;
+                self.recipients.clear();
+                self.status_message = Some(StatusMessage::Info(format!(
+                    "Recipients added, the archive now has {} recipients",
+                    count
+                )));
+            }
+            OperationResult::Upgraded(report) => {
+                self.operation_in_progress = false;
+                self.legacy = false;
+                self.status_message = Some(StatusMessage::Info(format!(
+                    "Converted to the new format: {} ({} entries)",
+                    report.output.display(),
+                    report.entries
+                )));
+            }
+            OperationResult::Listed(entries) => {
+                self.operation_in_progress = false;
+                self.status_message = None;
+                self.listing = Some(Listing { entries, checked: BTreeSet::new() });
+            }
+            OperationResult::Decrypted(report) => {
+                self.operation_in_progress = false;
+                self.listing = None;
+                let entries = report.entries.map(|n| format!(" ({} entries)", n)).unwrap_or_default();
+                let message = format!(
+                    "File has been decrypted to: {}{} (Please select folder/file again to refresh view)",
+                    report.output.display(),
+                    entries
+                );
+                // 有不安全条目被跳过时逐条列出，用错误颜色提醒
+                self.status_message = Some(if report.rejected.is_empty() {
+                    StatusMessage::Info(message)
+                } else {
+                    let skipped: Vec<String> = report.rejected
+                        .iter()
+                        .map(|rejected| format!("  {}: {}", rejected.path, rejected.reason))
+                        .collect();
+                    StatusMessage::Error(format!(
+                        "{}\n{} unsafe entries were skipped:\n{}",
+                        message,
+                        report.rejected.len(),
+                        skipped.join("\n")
+                    ))
+                });
+                self.selected_paths.clear(); // 清除选中路径，强制用户重新选择
+            }
+            OperationResult::Error(encryptor::EncryptorError::OutputExists(path)) => {
+                self.operation_in_progress = false;
+                self.status_message = None;
+                self.conflict = Some(path);
+            }
+            OperationResult::Error(encryptor::EncryptorError::Cancelled) => {
+                self.operation_in_progress = false;
+                self.status_message = Some(StatusMessage::Info("Operation cancelled, partial output removed".to_string()));
+            }
+            OperationResult::Error(error) => {
+                self.operation_in_progress = false;
+                // 密码错误时清空输入框，方便重新输入
+                if let encryptor::EncryptorError::WrongPassword = error {
+                    self.password.clear();
+                }
+                self.status_message = Some(StatusMessage::Error(format!("Error: {}", error)));
+            }
+            OperationResult::None => {}
+        }
+
+        self.conflict_dialog(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            // 居中显示标题
+            ui.vertical_centered(|ui| {
+                ui.heading("Folder Operations");
+            });
+            ui.add_space(10.0);
+
+            // 用一个框包裹主要内容
+            egui::Frame::none()
+                .inner_margin(egui::Margin::same(20.0))
+                .fill(ui.style().visuals.window_fill)
+                .stroke(ui.style().visuals.widgets.noninteractive.bg_stroke)
+                .rounding(egui::Rounding::same(8.0))
+                .show(ui, |ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.horizontal(|ui| {
+                            for (mode, label) in [
+                                (Mode::Encrypt, "Encrypt Mode"),
+                                (Mode::Decrypt, "Decrypt Mode"),
+                                (Mode::Verify, "Verify Mode"),
+                            ] {
+                                if ui.radio_value(&mut self.mode, mode, label).clicked() {
+                                    self.selected_paths.clear();
+                                    self.destination = None;
+                                    self.listing = None;
+                                    self.verify_report = None;
+                                    self.status_message = None;
+                                }
+                            }
+                        });
+
+                        ui.add_space(10.0);
+
+                        if self.mode == Mode::Encrypt {
+                            ui.horizontal(|ui| {
+                                if ui.button("Select Folder to Encrypt").clicked() {
+                                    // 选择文件夹进行加密
+                                    if let Some(dir) = self.file_dialog().pick_folder() {
+                                        self.set_selection(vec![dir]);
+                                    }
+                                }
+                                if ui.button("Select Files to Encrypt").clicked() {
+                                    // 可以一次选择多个文件，打包到同一个归档中
+                                    if let Some(files) = self.file_dialog().pick_files() {
+                                        self.set_selection(files);
+                                    }
+                                }
+                                // 追加到已选内容中，用于组合多个文件夹和文件
+                                if !self.selected_paths.is_empty()
+                                    && ui.button("Add Folder").clicked()
+                                    && let Some(dir) = self.file_dialog().pick_folder()
+                                {
+                                    let mut paths = self.selected_paths.clone();
+                                    paths.push(dir);
+                                    self.set_selection(paths);
+                                }
+                            });
+                        } else if ui.button(if self.mode == Mode::Verify { "Select File to Verify" } else { "Select File to Decrypt" }).clicked() {
+                            // 选择加密文件进行解密
+                            if let Some(file) = self.file_dialog()
+                                .add_filter("Encrypted files", ARCHIVE_EXTENSIONS)
+                                // 旧版文件可能是其他扩展名
+                                .add_filter("All files", &["*"])
+                                .set_title("Select encrypted file")
+                                .pick_file()
+                            {
+                                self.set_selection(vec![file]);
+                            }
+                        }
+
+                        ui.add_space(10.0);
+
+                        ui.horizontal(|ui| {
+                            ui.label("Password:");
+
+                            let _response = ui.add(
+                                egui::TextEdit::singleline(&mut self.password)
+                                    .password(!self.show_password) // 当show_password为false时显示密码掩码
+                            );
+
+                            // 添加一个切换密码显示状态的按钮
+                            if ui.button(if self.show_password { "Hide" } else { "Show" }).clicked() {
+                                self.show_password = !self.show_password;
+                            }
+                        });
+
+                        ui.add_space(10.0);
+
+                        match self.mode {
+                            Mode::Encrypt => {
+                                self.format_settings_ui(ui);
+                                // age 格式的密码固定使用 scrypt，且不压缩
+                                if self.format == encryptor::ArchiveFormat::Native {
+                                    self.kdf_settings_ui(ui);
+                                    self.compression_settings_ui(ui);
+                                }
+                                if !self.password_only() {
+                                    self.recipients_ui(ui);
+                                }
+                                ui.add_space(10.0);
+                            }
+                            Mode::Decrypt => {
+                                if self.legacy {
+                                    ui.colored_label(
+                                        ui.style().visuals.warn_fg_color,
+                                        "This file was made by an older version with OpenSSL and is not protected against tampering. \
+                                         Use \"Upgrade to New Format\" to re-encrypt it.",
+                                    );
+                                    ui.add_space(10.0);
+                                }
+                                self.identities_ui(ui);
+                                self.recipients_ui(ui);
+                                ui.add_space(10.0);
+                            }
+                            Mode::Verify => {
+                                self.identities_ui(ui);
+                                ui.add_space(10.0);
+                            }
+                        }
+
+                        if !self.selected_paths.is_empty() && self.mode != Mode::Verify {
+                            self.destination_ui(ui);
+                            let (label, hint) = match self.mode {
+                                Mode::Encrypt => ("Don't record file owners", "Store user and group as root, e.g. when sharing the archive"),
+                                _ => ("Don't restore file owners", "Extracted files belong to the current user"),
+                            };
+                            ui.checkbox(&mut self.skip_ownership, label).on_hover_text(hint);
+                            ui.add_space(10.0);
+                        }
+
+                        ui.add_enabled_ui(!self.selected_paths.is_empty() && !self.operation_in_progress && self.conflict.is_none(), |ui| {
+                            match self.mode {
+                                Mode::Encrypt => {
+                                    if ui.button("Encrypt").clicked() {
+                                        self.start_encrypt(ctx, encryptor::ConflictPolicy::Fail);
+                                    }
+                                }
+                                Mode::Decrypt => {
+                                    ui.horizontal(|ui| {
+                                        if ui.button("Decrypt File").clicked() {
+                                            self.extract_only.clear();
+                                            self.start_decrypt(ctx, encryptor::ConflictPolicy::Fail);
+                                        }
+                                        if ui.button("List Contents").clicked() {
+                                            self.start_list(ctx);
+                                        }
+                                        if self.legacy {
+                                            if ui.button("Upgrade to New Format").on_hover_text("Replace this file with one in the current format, keeping the password").clicked() {
+                                                self.start_upgrade(ctx);
+                                            }
+                                        } else if ui.button("Add Recipients").on_hover_text("Let the recipients above open this archive too").clicked() {
+                                            self.start_add_recipients(ctx);
+                                        }
+                                    });
+                                }
+                                Mode::Verify => {
+                                    if ui.button("Verify").clicked() {
+                                        self.start_verify(ctx);
+                                    }
+                                }
+                            }
+                        });
+
+                        ui.add_space(15.0);
+
+                        if !self.selected_paths.is_empty() {
+                            ui.horizontal(|ui| {
+                                match self.selected_paths.as_slice() {
+                                    [path] => ui.label(format!("Selected path: {}", path.display())),
+                                    paths => ui.label(format!("Selected {} items", paths.len()))
+                                        .on_hover_text(paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join("\n")),
+                                };
+
+                                // 添加刷新按钮
+                                if ui.button("🔄 Refresh").clicked() {
+                                    // 清除选中路径，强制用户重新选择
+                                    self.selected_paths.clear();
+                                    self.listing = None;
+                                    self.verify_report = None;
+                                    self.status_message = Some(StatusMessage::Info("Please select a folder or file again to see latest changes".to_string()));
+                                }
+                            });
+                        }
+
+                        if let Some(ref message) = self.status_message {
+                            ui.add_space(10.0);
+                            let (text_color, text) = match message {
+                                StatusMessage::Error(text) => (ui.style().visuals.error_fg_color, text),
+                                StatusMessage::Info(text) => (ui.style().visuals.text_color(), text),
+                            };
+
+                            ui.colored_label(text_color, text);
+                        }
+
+                        if self.operation_in_progress {
+                            ui.add_space(10.0);
+                            self.progress_ui(ui);
+                            self.cancel_ui(ui);
+                        }
+
+                        if self.listing.is_some() {
+                            ui.add_space(10.0);
+                            self.listing_ui(ui, ctx);
+                        }
+
+                        if self.verify_report.is_some() {
+                            ui.add_space(10.0);
+                            self.verify_report_ui(ui);
+                        }
+                    });
+                });
+        });
+    }
+}
+
+// 按路径组织的归档条目，用于树形显示
+#[derive(Default)]
+struct TreeNode<'a> {
+    entry: Option<&'a encryptor::ArchiveEntry>,
+    children: BTreeMap<String, TreeNode<'a>>,
+}
+
+impl<'a> TreeNode<'a> {
+    fn build(entries: &'a [encryptor::ArchiveEntry]) -> Self {
+        let mut root = TreeNode::default();
+        for entry in entries {
+            // 旧格式中的目录条目以 "/" 结尾
+            let node = entry.path
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .fold(&mut root, |node, part| node.children.entry(part.to_string()).or_default());
+            node.entry = Some(entry);
+        }
+        root
+    }
+
+    // parent_checked 表示上级文件夹已勾选，此时子条目一定会被解压，复选框只做显示
+    fn ui(&self, ui: &mut egui::Ui, name: &str, path: &str, parent_checked: bool, checked: &mut BTreeSet<String>) {
+        let details = self.entry.map(entry_details).unwrap_or_default();
+        let is_directory = !self.children.is_empty()
+            || self.entry.is_some_and(|entry| entry.kind == encryptor::EntryKind::Directory);
+        let is_checked = parent_checked || checked.contains(path);
+
+        let checkbox = |ui: &mut egui::Ui, checked: &mut BTreeSet<String>, label: String| {
+            let mut value = is_checked;
+            let response = ui.add_enabled(!parent_checked, egui::Checkbox::new(&mut value, label));
+            if response.changed() {
+                if value {
+                    // 勾选文件夹已包含其全部内容，去掉其中单独勾选的条目
+                    let prefix = format!("{}/", path);
+                    checked.retain(|other| !other.starts_with(&prefix));
+                    checked.insert(path.to_string());
+                } else {
+                    checked.remove(path);
+                }
+            }
+        };
+
+        if is_directory {
+            let id = ui.make_persistent_id(path);
+            egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+                .show_header(ui, |ui| {
+                    checkbox(ui, checked, format!("📁 {}", name));
+                    ui.weak(details);
+                })
+                .body(|ui| {
+                    for (child_name, child) in &self.children {
+                        child.ui(ui, child_name, &format!("{}/{}", path, child_name), is_checked, checked);
+                    }
+                });
+        } else {
+            ui.horizontal(|ui| {
+                checkbox(ui, checked, name.to_string());
+                ui.weak(details);
+            });
+        }
+    }
+}
+
+// 大小、修改时间和权限
+fn entry_details(entry: &encryptor::ArchiveEntry) -> String {
+    let mut details = Vec::new();
+    match entry.kind {
+        encryptor::EntryKind::File => details.push(format_bytes(entry.size)),
+        encryptor::EntryKind::Symlink => details.push("symlink".to_string()),
+        _ => {}
+    }
+    if let Some(modified) = entry.modified {
+        details.push(format_time(modified));
+    }
+    if let Some(permissions) = entry.permissions() {
+        details.push(permissions);
+    }
+    details.join("  ")
+}
+
+// 按本地时区显示到分钟
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -0,0 +1,27 @@
+//! 文件夹加密库
+//!
+//! 把文件夹打包并加密为单个 `.aes` 归档，或把归档解密还原。全部在进程内完成，
+//! 不依赖外部程序；`pw` 图形界面也是基于这里的接口实现的。
+//!
+//! - [`encrypt_folder`] / [`encrypt_paths`] / [`decrypt_folder`]：加解密入口，参数通过 [`EncryptOptions`] / [`DecryptOptions`] 传入
+//! - [`list_archive`] / [`verify_archive`] / [`archive_info`]：不解压地查看、校验归档，校验结果逐个条目给出
+//! - 归档可以加密给多个接收者（密码、X25519 或 ssh-ed25519 公钥，见 [`Recipient`]），
+//!   各自用密码或私钥（[`Identity`]）解密；[`add_recipients`] 添加接收者时不重新加密载荷
+//! - 也可以输出 age 文件（[`ArchiveFormat::Age`]），解密时自动识别，别人用 age 加密的普通文件同样可以打开
+//! - 还可以读写 7z 归档（[`ArchiveFormat::SevenZip`]），与 7-Zip 创建的加密归档互通
+//! - 需要发给只有系统自带解压工具的人时，可以输出 WinZip AES 加密的 zip 归档（[`ArchiveFormat::Zip`]），
+//!   解密时同样支持这类 zip
+//! - 旧版本调用 openssl 生成的 `.aes` 文件（[`ArchiveFormat::OpenSsl`]）可以直接解密，
+//!   或用 [`upgrade_archive`] 转换为当前格式
+//! - 进度通过 [`ProgressCallback`] 回调上报，任务可通过 [`CancelToken`] 取消
+//! - 失败时返回 [`EncryptorError`]，可按类型区分密码错误、文件损坏等情况
+
+pub mod encryptor;
+
+pub use encryptor::{
+    AddRecipientsOptions, ArchiveEntry, ArchiveFormat, ArchiveInfo, CancelToken, CheckStatus, Cipher, Compression, ConflictPolicy, DecryptOptions,
+    DecryptReport, EncryptOptions, EncryptReport, EncryptorError, EntryCheck, EntryKind, Identity, Kdf, Phase, Progress,
+    ProgressCallback, Recipient, RecipientKind, RejectReason, RejectedEntry, UpgradeOptions, VerifyOptions, VerifyReport,
+    add_recipients, archive_info, calibrate_kdf, decrypt_folder, encrypt_folder, encrypt_paths, list_archive, upgrade_archive,
+    verify_archive,
+};